@@ -1,18 +1,82 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
 use windows::{
-    Win32::{Foundation::LUID, Graphics::Dxgi::IDXGIAdapter1},
+    Win32::{
+        Foundation::LUID,
+        Graphics::Dxgi::{CreateDXGIFactory1, IDXGIAdapter1, IDXGIFactory1},
+    },
     core::Result,
 };
 
+#[cfg(windows)]
+use crate::windows_utils::dxgi::DxgiAdapterIter;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Luid {
+    pub high_part: i32,
+    pub low_part: u32,
+}
+
+#[cfg(windows)]
+impl From<LUID> for Luid {
+    fn from(value: LUID) -> Self {
+        Self {
+            high_part: value.HighPart,
+            low_part: value.LowPart,
+        }
+    }
+}
+
+impl Display for Luid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#010X}_{:#010X}", self.high_part, self.low_part)
+    }
+}
+
+impl FromStr for Luid {
+    type Err = String;
+
+    /// Parses the `0x00000000_0x0000C2D3` format used by `Display` and the
+    /// GPU performance counters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_part = |part: &str| {
+            part.strip_prefix("0x")
+                .or_else(|| part.strip_prefix("0X"))
+                .and_then(|x| u32::from_str_radix(x, 16).ok())
+                .ok_or_else(|| format!("\"{}\" is not a valid LUID", s))
+        };
+        let (high_part, low_part) = s
+            .split_once('_')
+            .ok_or_else(|| format!("\"{}\" is not a valid LUID", s))?;
+        Ok(Self {
+            high_part: parse_part(high_part)? as i32,
+            low_part: parse_part(low_part)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Adapter {
     pub name: String,
-    pub luid: LUID,
+    pub luid: Luid,
 }
 
+#[cfg(windows)]
 impl Adapter {
+    pub fn enumerate_all() -> Result<Vec<Self>> {
+        let dxgi_factory: IDXGIFactory1 = unsafe { CreateDXGIFactory1()? };
+        dxgi_factory
+            .iter_adapters()
+            .map(|adapter| Self::from_dxgi_adapter(&adapter))
+            .collect()
+    }
+
     pub fn from_dxgi_adapter(adapter: &IDXGIAdapter1) -> Result<Self> {
         unsafe {
             let desc = adapter.GetDesc1()?;
-            let luid = desc.AdapterLuid;
+            let luid = desc.AdapterLuid.into();
 
             let name_utf16 = &desc.Description[..desc
                 .Description
@@ -0,0 +1,426 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adapter::Adapter,
+    engine::{GpuEngineInstance, Series},
+    memory::GpuMemoryInstance,
+    plan::PassKind,
+    process::Process,
+    results::PassResult,
+    runner::{format_comparison, format_series},
+    sampling::sample_values,
+    scenario::pass_display_name,
+    significance::{Comparison, welch_t_test},
+    stats::SampleStats,
+};
+
+/// Statistics for one pass of the scenario, aggregated across iterations.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PassSummary {
+    pub name: String,
+    pub kind: PassKind,
+    pub iterations: usize,
+    pub duration_ms: u64,
+    /// The average number of frames captured per iteration, if the pass used
+    /// a capture sink.
+    pub mean_frames: Option<f64>,
+    pub adapters: Vec<AdapterSummary>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdapterSummary {
+    #[serde(flatten)]
+    pub series: Series,
+    /// Statistics over the samples of every iteration.
+    pub pooled: SampleStats,
+    /// Statistics over the mean of each iteration.
+    pub iteration_means: SampleStats,
+    /// The variance of the iteration means.
+    pub between_iteration_variance: f64,
+    /// The difference from the pooled samples of every baseline pass, if
+    /// this is a capture pass.
+    pub comparison: Option<Comparison>,
+    /// The mean of each counter instance, if the source reported them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instances: Vec<InstanceSummary>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InstanceSummary {
+    pub name: String,
+    pub mean: f64,
+}
+
+/// Groups passes by name and aggregates each adapter across iterations.
+/// Summaries are ordered by kind, then by the order the passes were first
+/// recorded in.
+pub fn summarize_passes(passes: &[PassResult], alpha: f64) -> Vec<PassSummary> {
+    let baseline = pooled_samples_where(passes, |pass| pass.kind == PassKind::Baseline);
+    let mut names: Vec<(&str, PassKind)> = Vec::new();
+    for pass in passes {
+        if !names.iter().any(|(name, _)| *name == pass.name) {
+            names.push((&pass.name, pass.kind));
+        }
+    }
+    names.sort_by_key(|(_, kind)| *kind);
+
+    names
+        .into_iter()
+        .map(|(name, kind)| {
+            let named_passes: Vec<_> = passes.iter().filter(|pass| pass.name == name).collect();
+
+            let frames: Vec<_> = named_passes.iter().filter_map(|pass| pass.frames).collect();
+            let mean_frames = if !frames.is_empty() {
+                Some(frames.iter().sum::<usize>() as f64 / frames.len() as f64)
+            } else {
+                None
+            };
+
+            let pooled = pooled_samples(passes, name);
+            let adapters = named_passes[0]
+                .adapters
+                .iter()
+                .zip(&pooled)
+                .enumerate()
+                .map(|(series_index, (first, samples))| {
+                    let iteration_means: Vec<_> = named_passes
+                        .iter()
+                        .filter_map(|pass| pass.adapters.get(series_index))
+                        .filter(|adapter| adapter.stats.count > 0)
+                        .map(|adapter| adapter.stats.mean)
+                        .collect();
+                    let iteration_means = SampleStats::from_samples(&iteration_means);
+                    let comparison = if kind != PassKind::Baseline {
+                        baseline
+                            .get(series_index)
+                            .and_then(|baseline| welch_t_test(baseline, samples, alpha))
+                    } else {
+                        None
+                    };
+                    AdapterSummary {
+                        series: first.series.clone(),
+                        pooled: SampleStats::from_samples(samples),
+                        iteration_means,
+                        between_iteration_variance: iteration_means.std_dev
+                            * iteration_means.std_dev,
+                        comparison,
+                        instances: instance_means(&named_passes, series_index),
+                    }
+                })
+                .collect();
+
+            PassSummary {
+                name: name.to_owned(),
+                kind,
+                iterations: named_passes.len(),
+                duration_ms: named_passes[0].duration_ms,
+                mean_frames,
+                adapters,
+            }
+        })
+        .collect()
+}
+
+/// Concatenates the sample values of every pass with the given name, by
+/// series.
+pub fn pooled_samples(passes: &[PassResult], name: &str) -> Vec<Vec<f64>> {
+    pooled_samples_where(passes, |pass| pass.name == name)
+}
+
+fn pooled_samples_where<F>(passes: &[PassResult], predicate: F) -> Vec<Vec<f64>>
+where
+    F: Fn(&PassResult) -> bool,
+{
+    let mut pooled: Vec<Vec<f64>> = Vec::new();
+    for pass in passes.iter().filter(|pass| predicate(pass)) {
+        for (series_index, adapter) in pass.adapters.iter().enumerate() {
+            if pooled.len() <= series_index {
+                pooled.resize(series_index + 1, Vec::new());
+            }
+            pooled[series_index].extend(sample_values(&adapter.samples));
+        }
+    }
+    pooled
+}
+
+/// Averages each counter instance of a series over every sample of the
+/// given passes. Instances are matched by name, since the set of instances
+/// can change between passes.
+fn instance_means(passes: &[&PassResult], series_index: usize) -> Vec<InstanceSummary> {
+    let mut totals: Vec<(&str, f64, usize)> = Vec::new();
+    for adapter in passes
+        .iter()
+        .filter_map(|pass| pass.adapters.get(series_index))
+    {
+        for sample in &adapter.samples {
+            for (name, value) in adapter.instances.iter().zip(&sample.instances) {
+                match totals.iter_mut().find(|(x, _, _)| x == name) {
+                    Some((_, total, count)) => {
+                        *total += value;
+                        *count += 1;
+                    }
+                    None => totals.push((name, *value, 1)),
+                }
+            }
+        }
+    }
+    totals
+        .into_iter()
+        .map(|(name, total, count)| InstanceSummary {
+            name: name.to_owned(),
+            mean: total / count as f64,
+        })
+        .collect()
+}
+
+/// A short name for a counter instance within its series.
+fn instance_label(name: &str) -> String {
+    if let Ok(instance) = name.parse::<GpuEngineInstance>() {
+        format!("phys_{} eng_{}", instance.phys, instance.eng)
+    } else if let Ok(instance) = name.parse::<GpuMemoryInstance>() {
+        format!("phys_{}", instance.phys)
+    } else {
+        name.to_owned()
+    }
+}
+
+pub fn print_summary(adapters: &[Adapter], processes: &[Process], summaries: &[PassSummary]) {
+    println!("Summary:");
+    for summary in summaries {
+        if summary.iterations > 1 {
+            println!(
+                "  {} across {} iterations:",
+                pass_display_name(&summary.name, summary.kind),
+                summary.iterations
+            );
+        } else {
+            println!("  {}:", pass_display_name(&summary.name, summary.kind));
+        }
+        if let Some(mean_frames) = summary.mean_frames {
+            println!("    Captured {:.0} frames on average", mean_frames);
+        }
+        for adapter_summary in &summary.adapters {
+            let series = &adapter_summary.series;
+            let unit = series.measurement.unit();
+            let mean = if summary.iterations > 1 {
+                format!(
+                    "{:6.2}{} (between-iteration std dev {:.2})",
+                    adapter_summary.pooled.mean, unit, adapter_summary.iteration_means.std_dev
+                )
+            } else {
+                format!("{:6.2}{}", adapter_summary.pooled.mean, unit)
+            };
+            println!("    {}", format_series(adapters, processes, series, &mean));
+            if let Some(comparison) = &adapter_summary.comparison {
+                println!(
+                    "        {} vs baseline",
+                    format_comparison(comparison, unit)
+                );
+            }
+            if adapter_summary.instances.len() > 1 {
+                for instance in &adapter_summary.instances {
+                    println!(
+                        "        {:6.2}{} - {}",
+                        instance.mean,
+                        unit,
+                        instance_label(&instance.name)
+                    );
+                }
+            }
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        engine::Measurement,
+        results::AdapterSamples,
+        sampling::{Sample, SourceSamples},
+    };
+
+    use super::*;
+
+    const ENG_0: &str = "pid_42_luid_0x00000000_0x0000C2D3_phys_0_eng_0_engtype_3D";
+    const ENG_1: &str = "pid_42_luid_0x00000000_0x0000C2D3_phys_0_eng_1_engtype_3D";
+
+    fn series() -> Series {
+        Series {
+            process_index: 0,
+            adapter_index: Some(0),
+            measurement: Measurement::Utilization {
+                engine: "3D".to_owned(),
+            },
+        }
+    }
+
+    fn pass(
+        name: &str,
+        kind: PassKind,
+        iteration: usize,
+        frames: Option<usize>,
+        samples: SourceSamples,
+    ) -> PassResult {
+        PassResult {
+            name: name.to_owned(),
+            kind,
+            iteration,
+            duration_ms: 1000,
+            frames,
+            adapters: vec![AdapterSamples {
+                series: series(),
+                stats: SampleStats::from_timed_samples(&samples.samples),
+                comparison: None,
+                instances: samples.instances,
+                samples: samples.samples,
+            }],
+        }
+    }
+
+    fn values(values: &[f64]) -> SourceSamples {
+        SourceSamples {
+            instances: Vec::new(),
+            samples: values
+                .iter()
+                .enumerate()
+                .map(|(i, x)| Sample::new(Duration::from_millis(500 * (i as u64 + 1)), *x))
+                .collect(),
+        }
+    }
+
+    fn engines(values: &[[f64; 2]]) -> SourceSamples {
+        SourceSamples {
+            instances: vec![ENG_0.to_owned(), ENG_1.to_owned()],
+            samples: values
+                .iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    Sample::from_instances(Duration::from_millis(500 * (i as u64 + 1)), x.to_vec())
+                })
+                .collect(),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn aggregates_iterations_of_each_pass() {
+        let passes = vec![
+            pass(
+                "wgc",
+                PassKind::Wgc,
+                0,
+                Some(60),
+                engines(&[[15.0, 5.0], [16.0, 6.0]]),
+            ),
+            pass(
+                "baseline",
+                PassKind::Baseline,
+                0,
+                None,
+                values(&[10.0, 12.0]),
+            ),
+            pass("dda", PassKind::Dda, 0, Some(40), values(&[30.0, 31.0])),
+            pass(
+                "baseline",
+                PassKind::Baseline,
+                1,
+                None,
+                values(&[11.0, 13.0]),
+            ),
+            pass(
+                "wgc",
+                PassKind::Wgc,
+                1,
+                Some(50),
+                engines(&[[18.0, 6.0], [20.0, 6.0]]),
+            ),
+        ];
+        let summaries = summarize_passes(&passes, 0.05);
+
+        // By kind, not by the order they were recorded in
+        let names: Vec<_> = summaries.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, ["baseline", "wgc", "dda"]);
+
+        let baseline = &summaries[0];
+        assert_eq!(baseline.iterations, 2);
+        assert_eq!(baseline.duration_ms, 1000);
+        assert_eq!(baseline.mean_frames, None);
+        let adapter = &baseline.adapters[0];
+        assert_eq!(adapter.series, series());
+        assert_eq!(adapter.pooled.count, 4);
+        assert_close(adapter.pooled.mean, 11.5);
+        assert_eq!(adapter.iteration_means.count, 2);
+        assert_close(adapter.iteration_means.mean, 11.5);
+        assert_close(adapter.between_iteration_variance, 0.5);
+        assert!(adapter.comparison.is_none());
+        assert!(adapter.instances.is_empty());
+
+        let wgc = &summaries[1];
+        assert_eq!(wgc.iterations, 2);
+        assert_eq!(wgc.mean_frames, Some(55.0));
+        let adapter = &wgc.adapters[0];
+        assert_close(adapter.pooled.mean, 23.0);
+        assert_close(adapter.iteration_means.mean, 23.0);
+        assert_close(adapter.between_iteration_variance, 8.0);
+        // Against the baseline samples of every iteration
+        let comparison = adapter.comparison.unwrap();
+        assert_close(comparison.delta, 11.5);
+        assert!(comparison.significant);
+        let instances: Vec<_> = adapter
+            .instances
+            .iter()
+            .map(|x| (x.name.as_str(), x.mean))
+            .collect();
+        assert_eq!(instances, [(ENG_0, 17.25), (ENG_1, 5.75)]);
+
+        let dda = &summaries[2];
+        assert_eq!(dda.iterations, 1);
+        assert_eq!(dda.mean_frames, Some(40.0));
+        assert_close(dda.adapters[0].between_iteration_variance, 0.0);
+        assert_close(dda.adapters[0].comparison.unwrap().delta, 19.0);
+    }
+
+    #[test]
+    fn iterations_without_samples_have_no_mean() {
+        let passes = vec![
+            pass("wgc", PassKind::Wgc, 0, Some(60), values(&[20.0, 22.0])),
+            pass("wgc", PassKind::Wgc, 1, Some(60), values(&[])),
+            pass("wgc", PassKind::Wgc, 2, Some(60), values(&[24.0, 26.0])),
+        ];
+        let summaries = summarize_passes(&passes, 0.05);
+        let adapter = &summaries[0].adapters[0];
+        assert_eq!(summaries[0].iterations, 3);
+        assert_eq!(adapter.pooled.count, 4);
+        assert_eq!(adapter.iteration_means.count, 2);
+        assert_close(adapter.iteration_means.mean, 23.0);
+    }
+
+    #[test]
+    fn passes_without_a_baseline_are_not_compared() {
+        let passes = vec![
+            pass("wgc", PassKind::Wgc, 0, Some(60), values(&[20.0, 22.0])),
+            pass("dda", PassKind::Dda, 0, None, values(&[30.0, 31.0])),
+        ];
+        let summaries = summarize_passes(&passes, 0.05);
+        assert_eq!(summaries.len(), 2);
+        assert!(summaries.iter().all(|x| x.adapters[0].comparison.is_none()));
+        assert_eq!(summaries[1].mean_frames, None);
+    }
+
+    #[test]
+    fn instances_are_labeled_by_engine() {
+        assert_eq!(instance_label(ENG_1), "phys_0 eng_1");
+        assert_eq!(instance_label("dwm.exe:42"), "dwm.exe:42");
+    }
+}
@@ -1,11 +1,55 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use wgcperf::{
+    engine::EngineSelection,
+    error::WgcPerfError,
+    monitor_selector::MonitorSelector,
+    plan::{PassKind, PassOrder},
+    scenario::{PassSpec, Scenario, ScenarioError, SinkOptions, WorkloadSettings},
+    thresholds::{Threshold, load_thresholds},
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// The index of the monitor to screenshot.
-    #[clap(short, long, default_value_t = 0)]
-    pub monitor: usize,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Records the baseline, WGC and DDA passes, or the passes of a scenario.
+    Run(RunArgs),
+    /// Captures the monitor with WGC until ENTER is pressed.
+    Adhoc(AdhocArgs),
+    /// Lists the monitors that can be captured.
+    ListMonitors,
+    /// Lists the adapters that utilization is recorded for.
+    ListAdapters,
+    /// Compares two saved results files.
+    Compare(CompareArgs),
+    /// Prints the summary of a saved results file.
+    Report(ReportArgs),
+    /// Computes the statistics of a process from a typeperf or relog CSV log.
+    Analyze(AnalyzeArgs),
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// The monitor to screenshot: an index, a name such as "DELL U2720Q", a
+    /// device name such as \\.\DISPLAY2, or "primary".
+    #[clap(short, long, default_value = "0")]
+    pub monitor: MonitorSelector,
+
+    /// A TOML or JSON file describing the passes to run. Replaces the
+    /// options that describe the default baseline, WGC and DDA passes.
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["duration", "rest", "iterations", "order", "sample_interval", "use_dirty_rects"]
+    )]
+    pub scenario: Option<PathBuf>,
 
     /// The duration of each test pass in ms.
     #[clap(short, long, default_value_t = 5000)]
@@ -15,15 +59,231 @@ pub struct Cli {
     #[clap(short, long, default_value_t = 1000)]
     pub rest: u64,
 
+    /// The number of times to repeat the baseline, WGC and DDA passes.
+    #[clap(short, long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub iterations: u64,
+
+    /// How the passes are ordered within each iteration.
+    #[clap(long, value_enum, default_value_t = PassOrder::Fixed)]
+    pub order: PassOrder,
+
+    /// The seed used to shuffle the passes. Overrides the scenario's seed.
+    /// Defaults to a random seed.
+    #[clap(long)]
+    pub seed: Option<u64>,
+
+    /// How often utilization is sampled during each test pass in ms.
+    #[clap(long, default_value_t = 500, value_parser = clap::value_parser!(u64).range(1..))]
+    pub sample_interval: u64,
+
     /// Sets the DirtyRegionMode to ReportAndRender (WGC only).
     #[clap(long)]
     pub use_dirty_rects: bool,
 
+    /// The GPU engine types to record, e.g. "3D,Copy,VideoProcessing", or "all".
+    /// Types an adapter doesn't have are recorded without samples.
+    #[clap(long, default_value = "3D")]
+    pub engines: EngineSelection,
+
+    /// Also records the dedicated and shared GPU memory used by the DWM on each adapter.
+    #[clap(long)]
+    pub memory: bool,
+
+    /// Also records the CPU usage of the DWM, in percent of every logical processor.
+    #[clap(long)]
+    pub cpu: bool,
+
+    /// Also records wgcperf's own process.
+    #[clap(long = "self")]
+    pub record_self: bool,
+
+    /// Also records the process with this pid. Can be repeated.
+    #[clap(long = "pid", value_name = "PID")]
+    pub pids: Vec<u32>,
+
+    /// Also records the process with this name in the current session, e.g. "obs64.exe". Can be repeated.
+    #[clap(long = "process-name", value_name = "NAME")]
+    pub process_names: Vec<String>,
+
+    /// The significance level used when comparing capture passes to the baseline.
+    #[clap(long, default_value_t = 0.05, value_parser = parse_alpha)]
+    pub alpha: f64,
+
     /// Enables verbose output.
     #[clap(short, long)]
     pub verbose: bool,
 
-    /// Enables verbose output.
-    #[clap(long, conflicts_with = "duration", conflicts_with = "rest")]
-    pub adhoc: bool,
+    /// Writes the configuration and samples of each pass to a JSON file.
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Writes every sample of each pass to a CSV file, one row per tick per series.
+    #[clap(long)]
+    pub csv: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub gate: GateArgs,
+}
+
+impl RunArgs {
+    /// Loads the scenario file, or builds the default baseline, WGC and DDA
+    /// scenario from the command line.
+    pub fn scenario(&self) -> Result<Scenario, ScenarioError> {
+        if let Some(path) = &self.scenario {
+            return Scenario::load(path);
+        }
+        let passes = PassKind::ALL
+            .into_iter()
+            .map(|kind| {
+                // Only capture passes are followed by a rest period
+                let rest = if kind == PassKind::Baseline {
+                    0
+                } else {
+                    self.rest
+                };
+                let mut pass = PassSpec::new(kind, self.duration, rest);
+                if kind == PassKind::Wgc {
+                    pass.sink = SinkOptions {
+                        use_dirty_rects: self.use_dirty_rects,
+                        ..Default::default()
+                    };
+                }
+                pass
+            })
+            .collect();
+        Scenario {
+            name: None,
+            iterations: self.iterations as usize,
+            order: self.order,
+            seed: None,
+            sample_interval_ms: self.sample_interval,
+            workload: WorkloadSettings::default(),
+            passes,
+        }
+        .validate()
+    }
+}
+
+#[derive(Args)]
+pub struct AdhocArgs {
+    /// The monitor to screenshot: an index, a name such as "DELL U2720Q", a
+    /// device name such as \\.\DISPLAY2, or "primary".
+    #[clap(short, long, default_value = "0")]
+    pub monitor: MonitorSelector,
+
+    /// Sets the DirtyRegionMode to ReportAndRender.
+    #[clap(long)]
+    pub use_dirty_rects: bool,
+}
+
+#[derive(Args)]
+pub struct AnalyzeArgs {
+    /// A typeperf or relog CSV export of \GPU Engine(*)\Utilization Percentage.
+    #[clap(long, value_name = "FILE")]
+    pub from_csv: PathBuf,
+
+    /// The process to analyze, e.g. the DWM's pid at the time of the log.
+    #[clap(long)]
+    pub pid: u32,
+
+    /// The name of the process, used to match it when comparing results files.
+    #[clap(long, default_value = "dwm.exe")]
+    pub process_name: String,
+
+    /// The kind of pass the log was recorded during. The pass is named after it.
+    #[clap(long, value_enum, default_value_t = PassKind::Baseline)]
+    pub kind: PassKind,
+
+    /// The GPU engine types to analyze, e.g. "3D,Copy,VideoProcessing", or "all".
+    #[clap(long, default_value = "3D")]
+    pub engines: EngineSelection,
+
+    /// Writes the samples and summary to a JSON results file.
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Writes every sample to a CSV file, one row per tick per series.
+    #[clap(long)]
+    pub csv: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub gate: GateArgs,
+}
+
+#[derive(Args)]
+pub struct CompareArgs {
+    /// The results file to compare against.
+    pub old: PathBuf,
+
+    /// The results file to compare.
+    pub new: PathBuf,
+
+    /// The significance level used when comparing the two files.
+    #[clap(long, default_value_t = 0.05, value_parser = parse_alpha)]
+    pub alpha: f64,
+
+    #[command(flatten)]
+    pub gate: GateArgs,
+}
+
+#[derive(Args)]
+pub struct ReportArgs {
+    /// The results file to report on.
+    pub results: PathBuf,
+
+    /// Writes every sample of each pass to a CSV file, one row per tick per series.
+    #[clap(long)]
+    pub csv: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub gate: GateArgs,
+}
+
+#[derive(Args)]
+pub struct GateArgs {
+    /// Fails with a non-zero exit code if the condition is true, e.g.
+    /// "wgc.overhead > 3" or "dda.frames < 90%". Conditions that can't be
+    /// checked, e.g. for a pass that wasn't recorded, also fail. Can be
+    /// repeated.
+    #[clap(long, value_name = "CONDITION")]
+    pub fail_if: Vec<Threshold>,
+
+    /// A file of --fail-if conditions, one per line.
+    #[clap(long, value_name = "FILE")]
+    pub thresholds: Option<PathBuf>,
+}
+
+impl GateArgs {
+    pub fn load(&self) -> Result<Vec<Threshold>, WgcPerfError> {
+        let mut thresholds = self.fail_if.clone();
+        if let Some(path) = &self.thresholds {
+            let loaded = load_thresholds(path).map_err(|error| {
+                // Lines that don't parse are reported as invalid data
+                if error.kind() == std::io::ErrorKind::InvalidData {
+                    WgcPerfError::config(format!(
+                        "Invalid thresholds file \"{}\": {}",
+                        path.display(),
+                        error
+                    ))
+                } else {
+                    WgcPerfError::io(format!("Could not read \"{}\"", path.display()), error)
+                }
+            })?;
+            thresholds.extend(loaded);
+        }
+        Ok(thresholds)
+    }
+}
+
+/// Parses a significance level, which must be strictly between 0 and 1.
+fn parse_alpha(value: &str) -> Result<f64, String> {
+    let alpha: f64 = value
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a number", value))?;
+    // Also rejects NaN
+    if alpha > 0.0 && alpha < 1.0 {
+        Ok(alpha)
+    } else {
+        Err(format!("{} is not between 0 and 1", value))
+    }
 }
@@ -0,0 +1,376 @@
+use crate::{
+    adapter::{Adapter, Luid},
+    aggregate::pooled_samples,
+    engine::Measurement,
+    plan::PassKind,
+    process::match_processes,
+    results::RunResults,
+    runner::format_comparison,
+    scenario::pass_display_name,
+    significance::{Comparison, welch_t_test},
+};
+
+/// The differences between two results files.
+#[derive(Clone, Debug)]
+pub struct ResultsComparison {
+    pub passes: Vec<PassDiff>,
+    /// Adapters from either file that had no counterpart in the other.
+    pub unmatched_adapters: Vec<String>,
+    /// Processes from either file that had no counterpart in the other.
+    pub unmatched_processes: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PassDiff {
+    pub name: String,
+    pub kind: PassKind,
+    pub old_mean_frames: Option<f64>,
+    pub new_mean_frames: Option<f64>,
+    pub adapters: Vec<AdapterDiff>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AdapterDiff {
+    /// The name of the process the series was measured for.
+    pub process: String,
+    /// The adapter's name and LUIDs, if the series is per adapter.
+    pub name: Option<String>,
+    pub measurement: Measurement,
+    pub old_luid: Option<Luid>,
+    pub new_luid: Option<Luid>,
+    pub old_mean: f64,
+    pub new_mean: f64,
+    /// The new mean minus the old mean, in the unit of the measurement.
+    pub delta: f64,
+    /// The delta relative to the old mean, or `None` if the old mean is zero.
+    pub percent_change: Option<f64>,
+    /// The significance of the change, computed from the pooled samples.
+    pub comparison: Option<Comparison>,
+}
+
+/// Pairs each adapter in `new` with one in `old`. Adapters are matched by
+/// both name and LUID first, then by name (LUIDs change across reboots),
+/// then by LUID.
+pub fn match_adapters(old: &[Adapter], new: &[Adapter]) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let mut old_used = vec![false; old.len()];
+    let mut new_used = vec![false; new.len()];
+    let matchers: [fn(&Adapter, &Adapter) -> bool; 3] = [
+        |old, new| old.name == new.name && old.luid == new.luid,
+        |old, new| old.name == new.name,
+        |old, new| old.luid == new.luid,
+    ];
+    for matcher in matchers {
+        for (new_index, new_adapter) in new.iter().enumerate() {
+            if new_used[new_index] {
+                continue;
+            }
+            if let Some(old_index) = old
+                .iter()
+                .enumerate()
+                .position(|(i, old_adapter)| !old_used[i] && matcher(old_adapter, new_adapter))
+            {
+                old_used[old_index] = true;
+                new_used[new_index] = true;
+                matches.push((old_index, new_index));
+            }
+        }
+    }
+    matches.sort_by_key(|(_, new_index)| *new_index);
+    matches
+}
+
+pub fn compare_results(old: &RunResults, new: &RunResults, alpha: f64) -> ResultsComparison {
+    let adapter_matches = match_adapters(&old.adapters, &new.adapters);
+    let mut unmatched_adapters = Vec::new();
+    for (i, adapter) in old.adapters.iter().enumerate() {
+        if !adapter_matches.iter().any(|(old_index, _)| *old_index == i) {
+            unmatched_adapters.push(format!("{} (old)", adapter.name));
+        }
+    }
+    for (i, adapter) in new.adapters.iter().enumerate() {
+        if !adapter_matches.iter().any(|(_, new_index)| *new_index == i) {
+            unmatched_adapters.push(format!("{} (new)", adapter.name));
+        }
+    }
+
+    let process_matches = match_processes(&old.processes, &new.processes);
+    let mut unmatched_processes = Vec::new();
+    for (i, process) in old.processes.iter().enumerate() {
+        if !process_matches.iter().any(|(old_index, _)| *old_index == i) {
+            unmatched_processes.push(format!("{} (old)", process));
+        }
+    }
+    for (i, process) in new.processes.iter().enumerate() {
+        if !process_matches.iter().any(|(_, new_index)| *new_index == i) {
+            unmatched_processes.push(format!("{} (new)", process));
+        }
+    }
+
+    // Passes are matched by name
+    let mut passes = Vec::new();
+    for new_summary in &new.summary {
+        let Some(old_summary) = old.summary.iter().find(|x| x.name == new_summary.name) else {
+            continue;
+        };
+        let old_samples = pooled_samples(&old.passes, &old_summary.name);
+        let new_samples = pooled_samples(&new.passes, &new_summary.name);
+
+        // Series are matched by process, adapter and measurement
+        let adapters = new_summary
+            .adapters
+            .iter()
+            .enumerate()
+            .filter_map(|(new_series_index, new_series)| {
+                let new_process_index = new_series.series.process_index;
+                let (old_process_index, _) = process_matches
+                    .iter()
+                    .find(|(_, x)| *x == new_process_index)?;
+                let new_index = new_series.series.adapter_index;
+                let old_index = match new_index {
+                    Some(new_index) => {
+                        let (old_index, _) =
+                            adapter_matches.iter().find(|(_, x)| *x == new_index)?;
+                        Some(*old_index)
+                    }
+                    None => None,
+                };
+                let old_series_index = old_summary.adapters.iter().position(|x| {
+                    x.series.process_index == *old_process_index
+                        && x.series.adapter_index == old_index
+                        && x.series.measurement == new_series.series.measurement
+                })?;
+                let empty = Vec::new();
+                let old_samples = old_samples.get(old_series_index).unwrap_or(&empty);
+                let new_samples = new_samples.get(new_series_index).unwrap_or(&empty);
+                let old_mean = old_summary.adapters[old_series_index].pooled.mean;
+                let new_mean = new_series.pooled.mean;
+                let delta = new_mean - old_mean;
+                Some(AdapterDiff {
+                    process: new.processes[new_process_index].name.clone(),
+                    name: new_index.map(|x| new.adapters[x].name.clone()),
+                    measurement: new_series.series.measurement.clone(),
+                    old_luid: old_index.map(|x| old.adapters[x].luid),
+                    new_luid: new_index.map(|x| new.adapters[x].luid),
+                    old_mean,
+                    new_mean,
+                    delta,
+                    percent_change: if old_mean != 0.0 {
+                        Some(delta / old_mean * 100.0)
+                    } else {
+                        None
+                    },
+                    comparison: welch_t_test(old_samples, new_samples, alpha),
+                })
+            })
+            .collect();
+
+        passes.push(PassDiff {
+            name: new_summary.name.clone(),
+            kind: new_summary.kind,
+            old_mean_frames: old_summary.mean_frames,
+            new_mean_frames: new_summary.mean_frames,
+            adapters,
+        });
+    }
+
+    ResultsComparison {
+        passes,
+        unmatched_adapters,
+        unmatched_processes,
+    }
+}
+
+pub fn print_results_comparison(comparison: &ResultsComparison) {
+    for pass in &comparison.passes {
+        println!("{}:", pass_display_name(&pass.name, pass.kind));
+        if let (Some(old_frames), Some(new_frames)) = (pass.old_mean_frames, pass.new_mean_frames) {
+            println!("  Frames: {:.0} -> {:.0}", old_frames, new_frames);
+        }
+        let multiple_processes = pass
+            .adapters
+            .iter()
+            .any(|x| x.process != pass.adapters[0].process);
+        for adapter in &pass.adapters {
+            let percent_change = adapter
+                .percent_change
+                .map(|x| format!("{:+.1}%", x))
+                .unwrap_or_else(|| "n/a".to_owned());
+            let unit = adapter.measurement.unit();
+            let mut change = format!(
+                "{} - {:6.2}{unit} -> {:6.2}{unit} ({} relative)",
+                adapter.measurement.label(),
+                adapter.old_mean,
+                adapter.new_mean,
+                percent_change,
+            );
+            if multiple_processes {
+                change = format!("{} - {}", adapter.process, change);
+            }
+            match &adapter.name {
+                Some(name) => println!("  {} - {}", change, name),
+                None => println!("  {}", change),
+            }
+            if let Some(significance) = &adapter.comparison {
+                println!("      {}", format_comparison(significance, unit));
+            } else {
+                println!("      not enough samples");
+            }
+        }
+        println!();
+    }
+    if !comparison.unmatched_adapters.is_empty() {
+        println!("Unmatched adapters:");
+        for adapter in &comparison.unmatched_adapters {
+            println!("  {}", adapter);
+        }
+    }
+    if !comparison.unmatched_processes.is_empty() {
+        println!("Unmatched processes:");
+        for process in &comparison.unmatched_processes {
+            println!("  {}", process);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{adapter, pass_result, process, run_results, utilization};
+
+    use super::*;
+
+    #[test]
+    fn reordered_adapters_are_matched() {
+        let old = [adapter("GPU A", 1), adapter("GPU B", 2)];
+        let new = [adapter("GPU B", 2), adapter("GPU A", 1)];
+        assert_eq!(match_adapters(&old, &new), [(1, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn adapters_fall_back_to_name_then_luid() {
+        // The LUID of A changed after a reboot, and B was renamed by a
+        // driver update
+        let old = [adapter("GPU A", 1), adapter("GPU B", 2)];
+        let new = [adapter("GPU A", 5), adapter("GPU B (new driver)", 2)];
+        assert_eq!(match_adapters(&old, &new), [(0, 0), (1, 1)]);
+
+        // Name and LUID beat name alone
+        let old = [adapter("GPU", 1), adapter("GPU", 2)];
+        let new = [adapter("GPU", 2)];
+        assert_eq!(match_adapters(&old, &new), [(1, 0)]);
+    }
+
+    #[test]
+    fn missing_adapters_are_left_unmatched() {
+        let old = [adapter("GPU A", 1), adapter("GPU B", 2)];
+        assert_eq!(match_adapters(&old, &[adapter("GPU A", 1)]), [(0, 0)]);
+        assert_eq!(match_adapters(&[], &old), []);
+    }
+
+    #[test]
+    fn series_are_matched_by_process_adapter_and_measurement() {
+        let series = [utilization(0, 0, "3D"), utilization(0, 1, "3D")];
+        let old = run_results(
+            vec![adapter("GPU A", 1), adapter("GPU B", 2)],
+            vec![process("dwm.exe", 1)],
+            vec![
+                pass_result(
+                    "baseline",
+                    PassKind::Baseline,
+                    None,
+                    &series,
+                    &[&[10.0; 4], &[0.0; 4]],
+                ),
+                pass_result(
+                    "wgc",
+                    PassKind::Wgc,
+                    Some(60),
+                    &series,
+                    &[&[20.0; 4], &[0.0; 4]],
+                ),
+                pass_result(
+                    "dda",
+                    PassKind::Dda,
+                    Some(60),
+                    &series,
+                    &[&[30.0; 4], &[0.0; 4]],
+                ),
+            ],
+        );
+        // The adapters are listed in the other order, the pids changed and
+        // an adapter and a process were added
+        let series = [
+            utilization(0, 0, "3D"),
+            utilization(0, 1, "3D"),
+            utilization(0, 2, "3D"),
+            utilization(1, 1, "3D"),
+        ];
+        let new = run_results(
+            vec![
+                adapter("GPU B", 2),
+                adapter("GPU A", 1),
+                adapter("GPU C", 3),
+            ],
+            vec![process("DWM.EXE", 7), process("explorer.exe", 8)],
+            vec![pass_result(
+                "wgc",
+                PassKind::Wgc,
+                Some(50),
+                &series,
+                &[&[5.0; 4], &[22.0, 22.0, 23.0, 21.0], &[1.0; 4], &[1.0; 4]],
+            )],
+        );
+
+        let comparison = compare_results(&old, &new, 0.05);
+        assert_eq!(comparison.unmatched_adapters, ["GPU C (new)"]);
+        assert_eq!(comparison.unmatched_processes, ["explorer.exe (8) (new)"]);
+        // Passes only in the old file are left out
+        assert_eq!(comparison.passes.len(), 1);
+        let pass = &comparison.passes[0];
+        assert_eq!(pass.name, "wgc");
+        assert_eq!(pass.old_mean_frames, Some(60.0));
+        assert_eq!(pass.new_mean_frames, Some(50.0));
+
+        // In the order of the new file
+        let adapters: Vec<_> = pass
+            .adapters
+            .iter()
+            .map(|x| {
+                (
+                    x.name.as_deref(),
+                    x.old_luid,
+                    x.new_luid,
+                    x.old_mean,
+                    x.new_mean,
+                )
+            })
+            .collect();
+        assert_eq!(
+            adapters,
+            [
+                (
+                    Some("GPU B"),
+                    Some(old.adapters[1].luid),
+                    Some(new.adapters[0].luid),
+                    0.0,
+                    5.0
+                ),
+                (
+                    Some("GPU A"),
+                    Some(old.adapters[0].luid),
+                    Some(new.adapters[1].luid),
+                    20.0,
+                    22.0
+                ),
+            ]
+        );
+        assert_eq!(pass.adapters[1].process, "DWM.EXE");
+        assert_eq!(pass.adapters[1].delta, 2.0);
+        assert_eq!(pass.adapters[1].percent_change, Some(10.0));
+        assert!(pass.adapters[1].comparison.is_some());
+
+        // A zero old mean has no relative change
+        assert_eq!(pass.adapters[0].delta, 5.0);
+        assert_eq!(pass.adapters[0].percent_change, None);
+    }
+}
@@ -0,0 +1,313 @@
+use std::path::{Path, PathBuf};
+
+use crate::process::Process;
+
+/// The executable names of the compositors we know about, in order of
+/// preference. Xorg comes last so that a Wayland compositor running Xwayland
+/// is picked over it.
+pub const COMPOSITOR_NAMES: &[&str] = &["dwm.exe", "gnome-shell", "kwin_wayland", "sway", "Xorg"];
+
+/// How to find the compositor process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompositorQuery {
+    /// The first process in the current session with one of these names,
+    /// in order of preference.
+    Names(Vec<String>),
+    /// A specific process, e.g. one given on the command line.
+    Pid(u32),
+    /// The first process in the given session with one of these names.
+    Session { session: u32, names: Vec<String> },
+}
+
+impl CompositorQuery {
+    /// Finds the DWM of the current session.
+    pub fn dwm() -> Self {
+        CompositorQuery::Names(vec!["dwm.exe".to_owned()])
+    }
+}
+
+impl Default for CompositorQuery {
+    fn default() -> Self {
+        CompositorQuery::Names(COMPOSITOR_NAMES.iter().map(|x| (*x).to_owned()).collect())
+    }
+}
+
+/// Looks up processes by name and session for `CompositorQuery`.
+pub trait CompositorLocator {
+    type Error;
+
+    /// The session our program is running in.
+    fn current_session(&self) -> Result<u32, Self::Error>;
+    /// Finds a process by executable name in a session.
+    fn find_in_session(&self, name: &str, session: u32) -> Result<Option<u32>, Self::Error>;
+    /// Looks up the executable name of a process, or `None` if it doesn't
+    /// exist.
+    fn process_name(&self, pid: u32) -> Result<Option<String>, Self::Error>;
+
+    fn locate(&self, query: &CompositorQuery) -> Result<Option<Process>, Self::Error> {
+        let (session, names) = match query {
+            CompositorQuery::Pid(pid) => {
+                return Ok(self
+                    .process_name(*pid)?
+                    .map(|name| Process { name, pid: *pid }));
+            }
+            // During RDP sessions, you'll have multiple sessions and
+            // multiple DWMs. We want the one the user is currently using.
+            CompositorQuery::Names(names) => (self.current_session()?, names),
+            CompositorQuery::Session { session, names } => (*session, names),
+        };
+        for name in names {
+            if let Some(pid) = self.find_in_session(name, session)? {
+                return Ok(Some(Process {
+                    name: name.clone(),
+                    pid,
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Finds processes through a proc filesystem. Sessions are the login
+/// sessions in `<proc_root>/<pid>/sessionid`, and processes are named after
+/// their command line's executable, or their `comm` if it has none.
+pub struct ProcCompositorLocator {
+    proc_root: PathBuf,
+    /// Used for `current_session`.
+    self_pid: u32,
+}
+
+impl ProcCompositorLocator {
+    pub fn new(proc_root: &Path) -> Self {
+        Self::with_self_pid(proc_root, std::process::id())
+    }
+
+    /// Uses `self_pid` as our own process, for proc trees that aren't the
+    /// real one.
+    pub fn with_self_pid(proc_root: &Path, self_pid: u32) -> Self {
+        Self {
+            proc_root: proc_root.to_owned(),
+            self_pid,
+        }
+    }
+
+    fn session(&self, pid: u32) -> std::io::Result<Option<u32>> {
+        let path = self.proc_root.join(pid.to_string()).join("sessionid");
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        text.trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| std::io::Error::other(format!("\"{}\" is not a session id", text.trim())))
+    }
+
+    /// The names a process can be found by: the executable of its command
+    /// line and its `comm`, which is truncated to 15 characters.
+    fn names(&self, pid: u32) -> std::io::Result<Option<(Option<String>, String)>> {
+        let dir = self.proc_root.join(pid.to_string());
+        let comm = match std::fs::read_to_string(dir.join("comm")) {
+            Ok(comm) => comm.trim_end_matches('\n').to_owned(),
+            // The process exited
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let executable = std::fs::read(dir.join("cmdline"))
+            .ok()
+            .and_then(|cmdline| {
+                let arg0 = cmdline.split(|x| *x == 0).next()?;
+                let arg0 = String::from_utf8_lossy(arg0);
+                // Only the executable, e.g. "/usr/bin/gnome-shell"
+                let arg0 = arg0.split(' ').next()?;
+                Path::new(arg0)
+                    .file_name()
+                    .map(|x| x.to_string_lossy().into_owned())
+            })
+            .filter(|x| !x.is_empty());
+        Ok(Some((executable, comm)))
+    }
+
+    /// The pids in the proc tree, in ascending order.
+    fn pids(&self) -> std::io::Result<Vec<u32>> {
+        let mut pids: Vec<u32> = std::fs::read_dir(&self.proc_root)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+            .collect();
+        pids.sort();
+        Ok(pids)
+    }
+}
+
+impl CompositorLocator for ProcCompositorLocator {
+    type Error = std::io::Error;
+
+    fn current_session(&self) -> std::io::Result<u32> {
+        self.session(self.self_pid)?.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Could not find the session of process {}", self.self_pid),
+            )
+        })
+    }
+
+    fn find_in_session(&self, name: &str, session: u32) -> std::io::Result<Option<u32>> {
+        for pid in self.pids()? {
+            let Some((executable, comm)) = self.names(pid)? else {
+                continue;
+            };
+            if executable.as_deref() != Some(name) && comm != name {
+                continue;
+            }
+            if self.session(pid)? == Some(session) {
+                return Ok(Some(pid));
+            }
+        }
+        Ok(None)
+    }
+
+    fn process_name(&self, pid: u32) -> std::io::Result<Option<String>> {
+        Ok(self
+            .names(pid)?
+            .map(|(executable, comm)| executable.unwrap_or(comm)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::ProcTree;
+
+    use super::*;
+
+    const SELF_PID: u32 = 100;
+
+    /// A proc tree with our own process in session 2.
+    fn proc_tree(name: &str) -> ProcTree {
+        let tree = ProcTree::new(&format!("compositor-{}", name));
+        add(&tree, SELF_PID, "wgcperf", "/usr/bin/wgcperf\0record\0", 2);
+        tree
+    }
+
+    /// Adds a process. `cmdline` is NUL separated like the real one.
+    fn add(tree: &ProcTree, pid: u32, comm: &str, cmdline: &str, session: u32) {
+        tree.write(pid, "comm", format!("{}\n", comm));
+        tree.write(pid, "cmdline", cmdline);
+        tree.write(pid, "sessionid", format!("{}\n", session));
+    }
+
+    fn self_locator(tree: &ProcTree) -> ProcCompositorLocator {
+        ProcCompositorLocator::with_self_pid(tree.root(), SELF_PID)
+    }
+
+    fn located(name: &str, pid: u32) -> Option<Process> {
+        Some(Process {
+            name: name.to_owned(),
+            pid,
+        })
+    }
+
+    #[test]
+    fn names_are_tried_in_order() {
+        let tree = proc_tree("order");
+        add(&tree, 20, "Xorg", "/usr/lib/xorg/Xorg\0:0\0", 2);
+        add(&tree, 30, "gnome-shell", "/usr/bin/gnome-shell\0", 2);
+        let locator = self_locator(&tree);
+
+        // Xorg has the lower pid, but the Wayland compositor is preferred
+        assert_eq!(
+            locator.locate(&CompositorQuery::default()).unwrap(),
+            located("gnome-shell", 30)
+        );
+        assert_eq!(
+            locator
+                .locate(&CompositorQuery::Names(vec![
+                    "Xorg".to_owned(),
+                    "gnome-shell".to_owned()
+                ]))
+                .unwrap(),
+            located("Xorg", 20)
+        );
+    }
+
+    #[test]
+    fn processes_are_found_by_cmdline_or_comm() {
+        let tree = proc_tree("names");
+        // comm is truncated to 15 characters
+        add(
+            &tree,
+            20,
+            "kwin_wayland_wr",
+            "/usr/bin/kwin_wayland\0--xwayland\0",
+            2,
+        );
+        // Kernel threads and zombies have no command line
+        add(&tree, 30, "sway", "", 2);
+        // Some processes rewrite their command line with spaces
+        add(&tree, 40, "Xorg", "/usr/lib/Xorg :0 -nolisten tcp", 2);
+        let locator = self_locator(&tree);
+
+        let find = |name: &str| {
+            locator
+                .locate(&CompositorQuery::Names(vec![name.to_owned()]))
+                .unwrap()
+        };
+        assert_eq!(find("kwin_wayland"), located("kwin_wayland", 20));
+        assert_eq!(find("kwin_wayland_wr"), located("kwin_wayland_wr", 20));
+        assert_eq!(find("sway"), located("sway", 30));
+        assert_eq!(find("Xorg"), located("Xorg", 40));
+        assert_eq!(find("gnome-shell"), None);
+
+        assert_eq!(
+            locator.process_name(20).unwrap().as_deref(),
+            Some("kwin_wayland")
+        );
+        assert_eq!(locator.process_name(30).unwrap().as_deref(), Some("sway"));
+    }
+
+    #[test]
+    fn only_the_current_session_is_searched() {
+        let tree = proc_tree("sessions");
+        add(&tree, 20, "gnome-shell", "/usr/bin/gnome-shell\0", 1);
+        add(&tree, 30, "gnome-shell", "/usr/bin/gnome-shell\0", 2);
+        add(&tree, 40, "gnome-shell", "/usr/bin/gnome-shell\0", 3);
+        let locator = self_locator(&tree);
+
+        assert_eq!(locator.current_session().unwrap(), 2);
+        assert_eq!(
+            locator.locate(&CompositorQuery::default()).unwrap(),
+            located("gnome-shell", 30)
+        );
+        let in_session = |session| CompositorQuery::Session {
+            session,
+            names: vec!["gnome-shell".to_owned()],
+        };
+        assert_eq!(
+            locator.locate(&in_session(3)).unwrap(),
+            located("gnome-shell", 40)
+        );
+        assert_eq!(locator.locate(&in_session(4)).unwrap(), None);
+    }
+
+    #[test]
+    fn pids_are_looked_up_directly() {
+        let tree = proc_tree("pids");
+        add(&tree, 20, "Xorg", "/usr/lib/xorg/Xorg\0:0\0", 7);
+        let locator = self_locator(&tree);
+
+        // In any session
+        assert_eq!(
+            locator.locate(&CompositorQuery::Pid(20)).unwrap(),
+            located("Xorg", 20)
+        );
+        assert_eq!(locator.locate(&CompositorQuery::Pid(21)).unwrap(), None);
+    }
+
+    #[test]
+    fn a_missing_session_is_an_error() {
+        let tree = proc_tree("no-session");
+        let locator = ProcCompositorLocator::with_self_pid(tree.root(), 999);
+        let error = locator.locate(&CompositorQuery::default()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,163 @@
+use std::{io::Write, path::Path};
+
+use crate::{adapter::Adapter, engine::Measurement, process::Process, results::PassResult};
+
+const CSV_HEADER: &str =
+    "pass,iteration,tick,elapsed_ms,process,pid,adapter_index,adapter_name,metric,engine,value";
+
+/// Writes the samples of each pass as a long-form table with one row per
+/// tick per series.
+pub fn write_samples_csv<W: Write>(
+    writer: &mut W,
+    adapters: &[Adapter],
+    processes: &[Process],
+    passes: &[PassResult],
+) -> std::io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for pass in passes {
+        let num_ticks = pass
+            .adapters
+            .iter()
+            .map(|x| x.samples.len())
+            .max()
+            .unwrap_or(0);
+        for tick in 0..num_ticks {
+            for adapter_samples in &pass.adapters {
+                let Some(sample) = adapter_samples.samples.get(tick) else {
+                    continue;
+                };
+                let engine = match &adapter_samples.series.measurement {
+                    Measurement::Utilization { engine } => engine.as_str(),
+                    _ => "",
+                };
+                // Series that aren't per adapter leave both columns empty
+                let adapter_index = adapter_samples.series.adapter_index;
+                let adapter_name = adapter_index
+                    .and_then(|x| adapters.get(x))
+                    .map(|x| x.name.as_str())
+                    .unwrap_or_default();
+                let process = processes.get(adapter_samples.series.process_index);
+                writeln!(
+                    writer,
+                    "{},{},{},{:.3},{},{},{},{},{},{},{}",
+                    escape_field(&pass.name),
+                    pass.iteration,
+                    tick,
+                    sample.elapsed_ms,
+                    escape_field(process.map(|x| x.name.as_str()).unwrap_or_default()),
+                    process.map(|x| x.pid.to_string()).unwrap_or_default(),
+                    adapter_index.map(|x| x.to_string()).unwrap_or_default(),
+                    escape_field(adapter_name),
+                    adapter_samples.series.measurement.metric_name(),
+                    escape_field(engine),
+                    sample.value
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn save_samples_csv<P: AsRef<Path>>(
+    path: P,
+    adapters: &[Adapter],
+    processes: &[Process],
+    passes: &[PassResult],
+) -> std::io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+    write_samples_csv(&mut writer, adapters, processes, passes)?;
+    writer.flush()
+}
+
+fn escape_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        adapter::Luid, engine::Series, plan::PassKind, results::AdapterSamples, sampling::Sample,
+        stats::SampleStats,
+    };
+
+    use super::*;
+
+    fn adapter_samples(series: Series, values: &[f64]) -> AdapterSamples {
+        let samples: Vec<_> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Sample::new(Duration::from_millis(500 * (i as u64 + 1)), *value))
+            .collect();
+        AdapterSamples {
+            series,
+            stats: SampleStats::from_timed_samples(&samples),
+            comparison: None,
+            instances: Vec::new(),
+            samples,
+        }
+    }
+
+    #[test]
+    fn writes_one_row_per_tick_per_series() {
+        let adapters = vec![Adapter {
+            name: "GPU \"A\", rev 2".to_owned(),
+            luid: Luid::default(),
+        }];
+        let processes = vec![Process {
+            name: "dwm.exe".to_owned(),
+            pid: 42,
+        }];
+        let utilization = Series {
+            process_index: 0,
+            adapter_index: Some(0),
+            measurement: Measurement::Utilization {
+                engine: "3D".to_owned(),
+            },
+        };
+        let cpu = Series {
+            process_index: 0,
+            adapter_index: None,
+            measurement: Measurement::Cpu,
+        };
+        let passes = vec![PassResult {
+            name: "wgc, dirty rects".to_owned(),
+            kind: PassKind::Wgc,
+            iteration: 1,
+            duration_ms: 1000,
+            frames: Some(60),
+            adapters: vec![
+                adapter_samples(utilization, &[10.0, 20.5]),
+                adapter_samples(cpu, &[1.5]),
+            ],
+        }];
+
+        let mut output = Vec::new();
+        write_samples_csv(&mut output, &adapters, &processes, &passes).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                CSV_HEADER,
+                "\"wgc, dirty rects\",1,0,500.000,dwm.exe,42,0,\"GPU \"\"A\"\", rev 2\",utilization,3D,10",
+                "\"wgc, dirty rects\",1,0,500.000,dwm.exe,42,,,cpu,,1.5",
+                "\"wgc, dirty rects\",1,1,1000.000,dwm.exe,42,0,\"GPU \"\"A\"\", rev 2\",utilization,3D,20.5",
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes_only_fields_that_need_it() {
+        assert_eq!(escape_field("baseline"), "baseline");
+        assert_eq!(escape_field("a,b"), "\"a,b\"");
+        assert_eq!(escape_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_field("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -0,0 +1,462 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    adapter::{Adapter, Luid},
+    engine::{Measurement, Series},
+    sampling::UtilizationSource,
+};
+
+/// The default root of the proc filesystem.
+pub const PROC_ROOT: &str = "/proc";
+
+/// The DRM usage stats of one client, parsed from an fdinfo file. See the
+/// kernel's `drm-usage-stats` documentation for the format.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DrmClient {
+    pub driver: String,
+    pub client_id: u64,
+    /// The PCI device, e.g. `0000:00:02.0`, if the driver reports one.
+    pub pdev: Option<String>,
+    /// The busy time of each engine in ns, in the order they are listed.
+    pub engines: Vec<(String, u64)>,
+    /// The number of engines of each class, for drivers that group them.
+    /// Engines that aren't listed have a capacity of one.
+    pub capacities: Vec<(String, u64)>,
+}
+
+impl DrmClient {
+    /// Parses an fdinfo file, returning `None` if the file descriptor isn't
+    /// a DRM client that reports usage stats.
+    pub fn parse(fdinfo: &str) -> Option<Self> {
+        let mut client = DrmClient::default();
+        let mut client_id = None;
+        for line in fdinfo.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if key == "drm-driver" {
+                client.driver = value.to_owned();
+            } else if key == "drm-client-id" {
+                client_id = value.parse().ok();
+            } else if key == "drm-pdev" {
+                client.pdev = Some(value.to_owned());
+            } else if let Some(engine) = key.strip_prefix("drm-engine-capacity-") {
+                if let Ok(capacity) = value.parse() {
+                    client.capacities.push((engine.to_owned(), capacity));
+                }
+            } else if let Some(engine) = key.strip_prefix("drm-engine-") {
+                let busy_ns = value.strip_suffix("ns").unwrap_or(value).trim();
+                if let Ok(busy_ns) = busy_ns.parse() {
+                    client.engines.push((engine.to_owned(), busy_ns));
+                }
+            }
+        }
+        client.client_id = client_id?;
+        Some(client)
+    }
+
+    /// Identifies the device the client belongs to: its PCI device, or its
+    /// driver for devices that aren't on PCI.
+    pub fn device(&self) -> &str {
+        self.pdev.as_deref().unwrap_or(&self.driver)
+    }
+
+    pub fn busy_ns(&self, engine: &str) -> Option<u64> {
+        self.engines
+            .iter()
+            .find(|(name, _)| name == engine)
+            .map(|(_, busy_ns)| *busy_ns)
+    }
+
+    pub fn capacity(&self, engine: &str) -> u64 {
+        self.capacities
+            .iter()
+            .find(|(name, _)| name == engine)
+            .map(|(_, capacity)| *capacity)
+            .unwrap_or(1)
+    }
+}
+
+/// Reads the DRM clients of a process from `<proc_root>/<pid>/fdinfo`. A
+/// client can be open through several file descriptors, so each client is
+/// only returned once.
+pub fn read_drm_clients(proc_root: &Path, pid: u32) -> std::io::Result<Vec<DrmClient>> {
+    let fdinfo_dir = proc_root.join(pid.to_string()).join("fdinfo");
+    let mut entries: Vec<_> = std::fs::read_dir(&fdinfo_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    // Sort by fd so that the order doesn't depend on the file system
+    entries.sort_by_key(|path| {
+        path.file_name()
+            .and_then(|x| x.to_str())
+            .and_then(|x| x.parse::<u64>().ok())
+    });
+
+    let mut clients: Vec<DrmClient> = Vec::new();
+    for path in entries {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            // The file descriptor was closed after we listed it
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+        let Some(client) = DrmClient::parse(&text) else {
+            continue;
+        };
+        if !clients
+            .iter()
+            .any(|x| x.client_id == client.client_id && x.device() == client.device())
+        {
+            clients.push(client);
+        }
+    }
+    Ok(clients)
+}
+
+/// A DRM device used by a process and the engines it reports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DrmDevice {
+    /// See `DrmClient::device`.
+    pub device: String,
+    pub driver: String,
+    pub engines: Vec<String>,
+}
+
+/// Lists the devices and engines a process has DRM clients for, in the
+/// order they first appear.
+pub fn list_drm_devices(proc_root: &Path, pid: u32) -> std::io::Result<Vec<DrmDevice>> {
+    let mut devices: Vec<DrmDevice> = Vec::new();
+    for client in read_drm_clients(proc_root, pid)? {
+        let index = match devices.iter().position(|x| x.device == client.device()) {
+            Some(index) => index,
+            None => {
+                devices.push(DrmDevice {
+                    device: client.device().to_owned(),
+                    driver: client.driver.clone(),
+                    engines: Vec::new(),
+                });
+                devices.len() - 1
+            }
+        };
+        for (engine, _) in &client.engines {
+            if !devices[index].engines.contains(engine) {
+                devices[index].engines.push(engine.clone());
+            }
+        }
+    }
+    Ok(devices)
+}
+
+/// Samples the utilization of one engine on one DRM device by a process,
+/// from the change in the busy time of its clients between samples. The
+/// utilization is relative to every engine of the class, so it stays within
+/// 0-100% for drivers that report a capacity.
+pub struct DrmEngineSource {
+    proc_root: PathBuf,
+    pid: u32,
+    device: String,
+    engine: String,
+    /// The time of the previous reading and the busy time of each client
+    /// then.
+    previous: Option<(Instant, HashMap<u64, u64>)>,
+}
+
+impl DrmEngineSource {
+    pub fn new(proc_root: &Path, pid: u32, device: &str, engine: &str) -> Self {
+        Self {
+            proc_root: proc_root.to_owned(),
+            pid,
+            device: device.to_owned(),
+            engine: engine.to_owned(),
+            previous: None,
+        }
+    }
+
+    pub fn start_at(&mut self, now: Instant) -> std::io::Result<()> {
+        let (busy_ns, _) = self.read_busy_ns()?;
+        self.previous = Some((now, busy_ns));
+        Ok(())
+    }
+
+    /// Reads the clients again and returns the utilization since the
+    /// previous reading.
+    pub fn sample_at(&mut self, now: Instant) -> std::io::Result<f64> {
+        let (busy_ns, capacity) = self.read_busy_ns()?;
+        let Some((previous_time, previous_busy_ns)) = self.previous.take() else {
+            return Err(std::io::Error::other("The source was sampled before start"));
+        };
+
+        // Clients that appeared since the previous reading were busy for
+        // all of their time within the interval
+        let delta_ns: u64 = busy_ns
+            .iter()
+            .map(|(client_id, busy_ns)| {
+                busy_ns.saturating_sub(previous_busy_ns.get(client_id).copied().unwrap_or(0))
+            })
+            .sum();
+        let elapsed_ns = now.saturating_duration_since(previous_time).as_nanos() as f64;
+        self.previous = Some((now, busy_ns));
+        if elapsed_ns <= 0.0 {
+            return Ok(0.0);
+        }
+        Ok(delta_ns as f64 / (elapsed_ns * capacity as f64) * 100.0)
+    }
+
+    /// The busy time of each client of the device, and the engine's
+    /// capacity.
+    fn read_busy_ns(&self) -> std::io::Result<(HashMap<u64, u64>, u64)> {
+        let mut busy_ns = HashMap::new();
+        let mut capacity = 1;
+        for client in read_drm_clients(&self.proc_root, self.pid)? {
+            if client.device() != self.device {
+                continue;
+            }
+            if let Some(client_busy_ns) = client.busy_ns(&self.engine) {
+                busy_ns.insert(client.client_id, client_busy_ns);
+                capacity = capacity.max(client.capacity(&self.engine));
+            }
+        }
+        Ok((busy_ns, capacity))
+    }
+}
+
+impl UtilizationSource for DrmEngineSource {
+    type Error = std::io::Error;
+
+    fn start(&mut self) -> std::io::Result<()> {
+        self.start_at(Instant::now())
+    }
+
+    fn sample(&mut self) -> std::io::Result<f64> {
+        self.sample_at(Instant::now())
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.previous = None;
+        Ok(())
+    }
+}
+
+/// A DRM device as an adapter, named after its driver and PCI device. DRM
+/// devices have no LUID.
+pub fn drm_adapter(device: &DrmDevice) -> Adapter {
+    let name = if device.device == device.driver {
+        device.driver.clone()
+    } else {
+        format!("{} ({})", device.driver, device.device)
+    };
+    Adapter {
+        name,
+        luid: Luid::default(),
+    }
+}
+
+/// The engines of every DRM device a process uses, laid out like the series
+/// of a live run.
+pub struct DrmSources {
+    /// One adapter per device, see `drm_adapter`.
+    pub adapters: Vec<Adapter>,
+    pub series: Vec<Series>,
+    /// The source of each series, in the order of `series`.
+    pub sources: Vec<Option<DrmEngineSource>>,
+}
+
+/// Lists a series and a source for every engine of every device the process
+/// uses, grouped by device like `build_series`.
+pub fn drm_engine_sources(
+    proc_root: &Path,
+    pid: u32,
+    process_index: usize,
+) -> std::io::Result<DrmSources> {
+    let devices = list_drm_devices(proc_root, pid)?;
+    let mut series = Vec::new();
+    let mut sources = Vec::new();
+    for (adapter_index, device) in devices.iter().enumerate() {
+        for engine in &device.engines {
+            series.push(Series {
+                process_index,
+                adapter_index: Some(adapter_index),
+                measurement: Measurement::Utilization {
+                    engine: engine.clone(),
+                },
+            });
+            sources.push(Some(DrmEngineSource::new(
+                proc_root,
+                pid,
+                &device.device,
+                engine,
+            )));
+        }
+    }
+    Ok(DrmSources {
+        adapters: devices.iter().map(drm_adapter).collect(),
+        series,
+        sources,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::test_support::ProcTree;
+
+    use super::*;
+
+    const PID: u32 = 1234;
+
+    fn write_fd(tree: &ProcTree, fd: u32, fdinfo: &str) {
+        tree.write(PID, &format!("fdinfo/{}", fd), fdinfo);
+    }
+
+    fn i915(client_id: u64, render_ns: u64, video_ns: u64) -> String {
+        format!(
+            "pos:\t0\nflags:\t02100002\nmnt_id:\t26\n\
+             drm-driver:\ti915\n\
+             drm-client-id:\t{}\n\
+             drm-pdev:\t0000:00:02.0\n\
+             drm-engine-render:\t{} ns\n\
+             drm-engine-video:\t{} ns\n\
+             drm-engine-capacity-video:\t2\n",
+            client_id, render_ns, video_ns
+        )
+    }
+
+    fn amdgpu(client_id: u64, gfx_ns: u64) -> String {
+        format!(
+            "pos:\t0\nflags:\t02100002\n\
+             drm-driver:\tamdgpu\n\
+             drm-client-id:\t{}\n\
+             drm-pdev:\t0000:03:00.0\n\
+             drm-engine-gfx:\t{} ns\n",
+            client_id, gfx_ns
+        )
+    }
+
+    #[test]
+    fn parses_usage_stats() {
+        let client = DrmClient::parse(&i915(7, 1000, 500)).unwrap();
+        assert_eq!(
+            client,
+            DrmClient {
+                driver: "i915".to_owned(),
+                client_id: 7,
+                pdev: Some("0000:00:02.0".to_owned()),
+                engines: vec![("render".to_owned(), 1000), ("video".to_owned(), 500)],
+                capacities: vec![("video".to_owned(), 2)],
+            }
+        );
+        assert_eq!(client.device(), "0000:00:02.0");
+        assert_eq!(client.capacity("video"), 2);
+        assert_eq!(client.capacity("render"), 1);
+        assert_eq!(client.busy_ns("copy"), None);
+
+        // Not a DRM file descriptor
+        assert_eq!(DrmClient::parse("pos:\t0\nflags:\t02\nmnt_id:\t15\n"), None);
+    }
+
+    #[test]
+    fn clients_are_read_once_per_device() {
+        let tree = ProcTree::new("drm-clients");
+        write_fd(&tree, 3, &i915(7, 1000, 0));
+        write_fd(&tree, 12, &amdgpu(7, 300));
+        // The same client through a duplicated file descriptor
+        write_fd(&tree, 5, &i915(7, 1000, 0));
+        write_fd(&tree, 4, "pos:\t0\nflags:\t02\n");
+
+        let clients = read_drm_clients(tree.root(), PID).unwrap();
+        let ids: Vec<(&str, u64)> = clients.iter().map(|x| (x.device(), x.client_id)).collect();
+        assert_eq!(ids, [("0000:00:02.0", 7), ("0000:03:00.0", 7)]);
+
+        let devices = list_drm_devices(tree.root(), PID).unwrap();
+        assert_eq!(
+            devices,
+            [
+                DrmDevice {
+                    device: "0000:00:02.0".to_owned(),
+                    driver: "i915".to_owned(),
+                    engines: vec!["render".to_owned(), "video".to_owned()],
+                },
+                DrmDevice {
+                    device: "0000:03:00.0".to_owned(),
+                    driver: "amdgpu".to_owned(),
+                    engines: vec!["gfx".to_owned()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn utilization_is_the_busy_time_over_the_interval() {
+        let tree = ProcTree::new("drm-utilization");
+        write_fd(&tree, 3, &i915(7, 1_000_000_000, 0));
+        let mut render = DrmEngineSource::new(tree.root(), PID, "0000:00:02.0", "render");
+        let start = Instant::now();
+        render.start_at(start).unwrap();
+
+        // 250ms more on the existing client, and a client that was opened
+        // since the last sample with 100ms
+        write_fd(&tree, 3, &i915(7, 1_250_000_000, 0));
+        write_fd(&tree, 6, &i915(8, 100_000_000, 0));
+        let value = render.sample_at(start + Duration::from_secs(1)).unwrap();
+        assert!((value - 35.0).abs() < 1e-9, "{}", value);
+
+        // Both clients are known now
+        write_fd(&tree, 3, &i915(7, 1_300_000_000, 0));
+        write_fd(&tree, 6, &i915(8, 150_000_000, 0));
+        let value = render
+            .sample_at(start + Duration::from_millis(1500))
+            .unwrap();
+        assert!((value - 20.0).abs() < 1e-9, "{}", value);
+    }
+
+    #[test]
+    fn utilization_is_relative_to_the_engine_capacity() {
+        let tree = ProcTree::new("drm-capacity");
+        write_fd(&tree, 3, &i915(7, 0, 0));
+        let mut video = DrmEngineSource::new(tree.root(), PID, "0000:00:02.0", "video");
+        let start = Instant::now();
+        video.start_at(start).unwrap();
+
+        // 500ms of one of two video engines
+        write_fd(&tree, 3, &i915(7, 0, 500_000_000));
+        let value = video.sample_at(start + Duration::from_secs(1)).unwrap();
+        assert!((value - 25.0).abs() < 1e-9, "{}", value);
+    }
+
+    #[test]
+    fn sampling_before_start_fails() {
+        let tree = ProcTree::new("drm-unstarted");
+        write_fd(&tree, 3, &i915(7, 0, 0));
+        let mut render = DrmEngineSource::new(tree.root(), PID, "0000:00:02.0", "render");
+        assert!(render.sample_at(Instant::now()).is_err());
+    }
+
+    #[test]
+    fn sources_are_grouped_by_device() {
+        let tree = ProcTree::new("drm-sources");
+        write_fd(&tree, 3, &i915(7, 0, 0));
+        write_fd(&tree, 4, &amdgpu(2, 0));
+
+        let sources = drm_engine_sources(tree.root(), PID, 1).unwrap();
+        let names: Vec<&str> = sources.adapters.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, ["i915 (0000:00:02.0)", "amdgpu (0000:03:00.0)"]);
+        let series: Vec<(Option<usize>, &str)> = sources
+            .series
+            .iter()
+            .map(|x| (x.adapter_index, x.measurement.label()))
+            .collect();
+        assert_eq!(
+            series,
+            [(Some(0), "render"), (Some(0), "video"), (Some(1), "gfx")]
+        );
+        assert!(sources.series.iter().all(|x| x.process_index == 1));
+        assert_eq!(sources.sources.len(), 3);
+    }
+}
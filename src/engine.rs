@@ -0,0 +1,537 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::adapter::Luid;
+
+/// The engine types Windows reports in the `engtype_` part of GPU Engine
+/// counter instances. Drivers may expose others, e.g. `Cuda`.
+pub const ENGINE_TYPES: [&str; 9] = [
+    "3D",
+    "Copy",
+    "VideoDecode",
+    "VideoEncode",
+    "VideoProcessing",
+    "Compute",
+    "Overlay",
+    "Security",
+    "Crypto",
+];
+
+/// The engine types to record utilization for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EngineSelection(pub Vec<String>);
+
+impl Default for EngineSelection {
+    fn default() -> Self {
+        Self(vec!["3D".to_owned()])
+    }
+}
+
+impl FromStr for EngineSelection {
+    type Err = String;
+
+    /// Parses `all` or a comma separated list such as `3D,Copy`. Known
+    /// engine types are matched case-insensitively, anything else is passed
+    /// through as a driver-specific engine type. `all` means every type in
+    /// `ENGINE_TYPES`, most of which a given GPU won't have.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("all") {
+            return Ok(Self(ENGINE_TYPES.iter().map(|x| x.to_string()).collect()));
+        }
+        let mut engines: Vec<String> = Vec::new();
+        for engine in s.split(',').map(str::trim) {
+            if engine.is_empty()
+                || !engine
+                    .chars()
+                    .all(|x| x.is_ascii_alphanumeric() || x == '_')
+            {
+                return Err(format!("\"{}\" is not a valid engine type", engine));
+            }
+            let engine = ENGINE_TYPES
+                .iter()
+                .find(|x| x.eq_ignore_ascii_case(engine))
+                .map(|x| x.to_string())
+                .unwrap_or_else(|| engine.to_owned());
+            if !engines.contains(&engine) {
+                engines.push(engine);
+            }
+        }
+        Ok(Self(engines))
+    }
+}
+
+/// What a series measures.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "metric", rename_all = "snake_case")]
+pub enum Measurement {
+    /// The utilization of one engine type, in percent.
+    Utilization { engine: String },
+    /// The dedicated GPU memory in use, in MiB.
+    DedicatedMemory,
+    /// The shared GPU memory in use, in MiB.
+    SharedMemory,
+    /// The processor time of the process, in percent of every logical
+    /// processor.
+    Cpu,
+}
+
+impl Measurement {
+    pub fn label(&self) -> &str {
+        match self {
+            Measurement::Utilization { engine } => engine,
+            Measurement::DedicatedMemory => "Dedicated memory",
+            Measurement::SharedMemory => "Shared memory",
+            Measurement::Cpu => "CPU",
+        }
+    }
+
+    /// The unit suffix used when printing values.
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Measurement::Utilization { .. } | Measurement::Cpu => "%",
+            Measurement::DedicatedMemory | Measurement::SharedMemory => " MiB",
+        }
+    }
+
+    /// The `metric` tag used in the results file.
+    pub fn metric_name(&self) -> &'static str {
+        match self {
+            Measurement::Utilization { .. } => "utilization",
+            Measurement::DedicatedMemory => "dedicated_memory",
+            Measurement::SharedMemory => "shared_memory",
+            Measurement::Cpu => "cpu",
+        }
+    }
+
+    pub fn is_utilization(&self) -> bool {
+        matches!(self, Measurement::Utilization { .. })
+    }
+}
+
+/// What a sample vector measures: one engine type or memory counter of a
+/// process on one adapter, or the CPU usage of a process.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Series {
+    /// The process the series was measured for.
+    pub process_index: usize,
+    /// The adapter the series was measured on, if it is per adapter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adapter_index: Option<usize>,
+    #[serde(flatten)]
+    pub measurement: Measurement,
+}
+
+/// Lists the series recorded for each process, grouped by process and then
+/// by adapter: the utilization of each engine type, followed by the
+/// dedicated and shared memory if `memory` is set. The CPU usage of each
+/// process comes after its adapters if `cpu` is set. Samplers return their
+/// sample vectors in this order.
+pub fn build_series(
+    process_count: usize,
+    adapter_count: usize,
+    engines: &[String],
+    memory: bool,
+    cpu: bool,
+) -> Vec<Series> {
+    let mut measurements: Vec<_> = engines
+        .iter()
+        .map(|engine| Measurement::Utilization {
+            engine: engine.clone(),
+        })
+        .collect();
+    if memory {
+        measurements.push(Measurement::DedicatedMemory);
+        measurements.push(Measurement::SharedMemory);
+    }
+    let mut series = Vec::new();
+    for process_index in 0..process_count {
+        for adapter_index in 0..adapter_count {
+            for measurement in &measurements {
+                series.push(Series {
+                    process_index,
+                    adapter_index: Some(adapter_index),
+                    measurement: measurement.clone(),
+                });
+            }
+        }
+        if cpu {
+            series.push(Series {
+                process_index,
+                adapter_index: None,
+                measurement: Measurement::Cpu,
+            });
+        }
+    }
+    series
+}
+
+/// Extracts the instance from a full counter path such as
+/// `\\MACHINE\GPU Engine(pid_..._engtype_3D)\Utilization Percentage`.
+pub fn counter_path_instance(path: &str) -> Result<&str, String> {
+    let start = path
+        .find('(')
+        .ok_or_else(|| format!("\"{}\" has no instance", path))?;
+    let end = path[start..]
+        .find(')')
+        .ok_or_else(|| format!("\"{}\" has no instance", path))?;
+    Ok(&path[start + 1..start + end])
+}
+
+/// The instance name of a GPU Engine counter, e.g.
+/// `pid_1234_luid_0x00000000_0x0000C2D3_phys_0_eng_0_engtype_3D`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GpuEngineInstance {
+    pub pid: u32,
+    pub luid: Luid,
+    /// The physical adapter within a linked adapter.
+    pub phys: u32,
+    /// The engine index within the physical adapter.
+    pub eng: u32,
+    /// The engine type. Some drivers leave this empty.
+    pub engtype: String,
+}
+
+impl GpuEngineInstance {
+    pub fn from_counter_path(path: &str) -> Result<Self, String> {
+        counter_path_instance(path)?.parse()
+    }
+}
+
+impl FromStr for GpuEngineInstance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("\"{}\" is not a GPU engine instance", s);
+        let rest = s.strip_prefix("pid_").ok_or_else(invalid)?;
+        let (pid, rest) = rest.split_once("_luid_").ok_or_else(invalid)?;
+        let (luid, rest) = rest.split_once("_phys_").ok_or_else(invalid)?;
+        let (phys, rest) = rest.split_once("_eng_").ok_or_else(invalid)?;
+        let (eng, engtype) = rest.split_once("_engtype_").ok_or_else(invalid)?;
+        Ok(Self {
+            pid: pid.parse().map_err(|_| invalid())?,
+            luid: luid.parse()?,
+            phys: phys.parse().map_err(|_| invalid())?,
+            eng: eng.parse().map_err(|_| invalid())?,
+            engtype: engtype.to_owned(),
+        })
+    }
+}
+
+impl Display for GpuEngineInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "pid_{}_luid_{}_phys_{}_eng_{}_engtype_{}",
+            self.pid, self.luid, self.phys, self.eng, self.engtype
+        )
+    }
+}
+
+/// A set of GPU Engine counter instances. Fields left as `None` match
+/// anything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GpuEngineQuery {
+    pub pid: Option<u32>,
+    pub luid: Option<Luid>,
+    pub phys: Option<u32>,
+    pub eng: Option<u32>,
+    pub engtype: Option<String>,
+}
+
+impl GpuEngineQuery {
+    /// Renders the instance part of a wildcard counter path. PDH only
+    /// expands a single `*` per instance, so fields after the first gap are
+    /// left out, except for the engine type at the end. Use `matches` to
+    /// filter the expanded instances.
+    pub fn instance_pattern(&self) -> String {
+        let fields = [
+            ("pid", self.pid.map(|x| x.to_string())),
+            ("luid", self.luid.map(|x| x.to_string())),
+            ("phys", self.phys.map(|x| x.to_string())),
+            ("eng", self.eng.map(|x| x.to_string())),
+        ];
+        let mut pattern = String::new();
+        let mut complete = true;
+        for (name, value) in fields {
+            match value {
+                Some(value) if complete => pattern.push_str(&format!("{}_{}_", name, value)),
+                _ => complete = false,
+            }
+        }
+        match (&self.engtype, complete) {
+            (Some(engtype), true) => pattern.push_str(&format!("engtype_{}", engtype)),
+            (Some(engtype), false) => pattern.push_str(&format!("*engtype_{}", engtype)),
+            (None, _) => pattern.push('*'),
+        }
+        pattern
+    }
+
+    /// The wildcard path for the utilization of the matching engines.
+    pub fn counter_path(&self) -> String {
+        format!(
+            r#"\GPU Engine({})\Utilization Percentage"#,
+            self.instance_pattern()
+        )
+    }
+
+    pub fn matches(&self, instance: &GpuEngineInstance) -> bool {
+        self.pid.is_none_or(|x| x == instance.pid)
+            && self.luid.is_none_or(|x| x == instance.luid)
+            && self.phys.is_none_or(|x| x == instance.phys)
+            && self.eng.is_none_or(|x| x == instance.eng)
+            && self.engtype.as_ref().is_none_or(|x| *x == instance.engtype)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSTANCE: &str = "pid_1234_luid_0x00000000_0x0000C2D3_phys_0_eng_3_engtype_3D";
+
+    fn luid(high_part: i32, low_part: u32) -> Luid {
+        Luid {
+            high_part,
+            low_part,
+        }
+    }
+
+    fn engines(s: &str) -> Vec<String> {
+        s.parse::<EngineSelection>().unwrap().0
+    }
+
+    #[test]
+    fn parses_engine_selections() {
+        assert_eq!(engines("all"), ENGINE_TYPES);
+        assert_eq!(engines(" ALL "), ENGINE_TYPES);
+        assert_eq!(engines("3D"), ["3D"]);
+        // Known types are normalized and duplicates dropped, in order
+        assert_eq!(
+            engines("copy, 3d,VIDEODECODE,Copy"),
+            ["Copy", "3D", "VideoDecode"]
+        );
+        // Anything else is a driver-specific type
+        assert_eq!(
+            engines("Cuda,3D,High_Priority_3D"),
+            ["Cuda", "3D", "High_Priority_3D"]
+        );
+        assert_eq!(EngineSelection::default().0, ["3D"]);
+    }
+
+    #[test]
+    fn rejects_invalid_engine_types() {
+        for s in [
+            "",
+            "3D,",
+            "3D,,Copy",
+            "Video Decode",
+            "3D;Copy",
+            "engtype_3D)",
+        ] {
+            assert!(s.parse::<EngineSelection>().is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn series_are_grouped_by_process_then_adapter() {
+        let series = build_series(2, 2, &engines("3D,Copy"), true, true);
+        let layout: Vec<_> = series
+            .iter()
+            .map(|x| (x.process_index, x.adapter_index, x.measurement.label()))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                (0, Some(0), "3D"),
+                (0, Some(0), "Copy"),
+                (0, Some(0), "Dedicated memory"),
+                (0, Some(0), "Shared memory"),
+                (0, Some(1), "3D"),
+                (0, Some(1), "Copy"),
+                (0, Some(1), "Dedicated memory"),
+                (0, Some(1), "Shared memory"),
+                (0, None, "CPU"),
+                (1, Some(0), "3D"),
+                (1, Some(0), "Copy"),
+                (1, Some(0), "Dedicated memory"),
+                (1, Some(0), "Shared memory"),
+                (1, Some(1), "3D"),
+                (1, Some(1), "Copy"),
+                (1, Some(1), "Dedicated memory"),
+                (1, Some(1), "Shared memory"),
+                (1, None, "CPU"),
+            ]
+        );
+    }
+
+    #[test]
+    fn series_without_memory_or_cpu() {
+        let series = build_series(1, 2, &engines("3D"), false, false);
+        assert_eq!(
+            series,
+            [
+                Series {
+                    process_index: 0,
+                    adapter_index: Some(0),
+                    measurement: Measurement::Utilization {
+                        engine: "3D".to_owned()
+                    },
+                },
+                Series {
+                    process_index: 0,
+                    adapter_index: Some(1),
+                    measurement: Measurement::Utilization {
+                        engine: "3D".to_owned()
+                    },
+                },
+            ]
+        );
+        // CPU usage doesn't need an adapter
+        let series = build_series(1, 0, &engines("3D"), true, true);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].measurement, Measurement::Cpu);
+        assert!(build_series(0, 2, &engines("all"), true, true).is_empty());
+    }
+
+    #[test]
+    fn instances_round_trip() {
+        let instance: GpuEngineInstance = INSTANCE.parse().unwrap();
+        assert_eq!(
+            instance,
+            GpuEngineInstance {
+                pid: 1234,
+                luid: luid(0, 0xC2D3),
+                phys: 0,
+                eng: 3,
+                engtype: "3D".to_owned(),
+            }
+        );
+        assert_eq!(instance.to_string(), INSTANCE);
+
+        let path = format!(
+            r#"\\MACHINE\GPU Engine({})\Utilization Percentage"#,
+            INSTANCE
+        );
+        assert_eq!(GpuEngineInstance::from_counter_path(&path), Ok(instance));
+    }
+
+    #[test]
+    fn empty_engine_types_round_trip() {
+        let text = "pid_8_luid_0x00000000_0x00010A2B_phys_1_eng_12_engtype_";
+        let instance: GpuEngineInstance = text.parse().unwrap();
+        assert_eq!(instance.engtype, "");
+        assert_eq!(instance.to_string(), text);
+    }
+
+    #[test]
+    fn negative_high_parts_round_trip() {
+        let text = "0xFFFFFFFF_0x0000C2D3";
+        let parsed: Luid = text.parse().unwrap();
+        assert_eq!(parsed, luid(-1, 0xC2D3));
+        assert_eq!(parsed.to_string(), text);
+
+        let instance = GpuEngineInstance {
+            luid: luid(i32::MIN, 1),
+            ..INSTANCE.parse().unwrap()
+        };
+        assert_eq!(
+            instance.to_string(),
+            "pid_1234_luid_0x80000000_0x00000001_phys_0_eng_3_engtype_3D"
+        );
+        assert_eq!(instance.to_string().parse(), Ok(instance));
+    }
+
+    #[test]
+    fn rejects_malformed_instances() {
+        for text in [
+            "",
+            "pid_1234",
+            "pid_x_luid_0x00000000_0x0000C2D3_phys_0_eng_3_engtype_3D",
+            "pid_1234_luid_00000000_0000C2D3_phys_0_eng_3_engtype_3D",
+            "pid_1234_luid_0x00000000_0x0000C2D3_phys_0_engtype_3D",
+        ] {
+            assert!(text.parse::<GpuEngineInstance>().is_err(), "{}", text);
+        }
+        assert!(
+            GpuEngineInstance::from_counter_path(r"\GPU Engine\Utilization Percentage").is_err()
+        );
+    }
+
+    #[test]
+    fn instance_patterns_stop_at_the_first_gap() {
+        let pid_only = GpuEngineQuery {
+            pid: Some(1234),
+            ..Default::default()
+        };
+        assert_eq!(pid_only.instance_pattern(), "pid_1234_*");
+
+        let pid_and_luid = GpuEngineQuery {
+            pid: Some(1234),
+            luid: Some(luid(0, 0xC2D3)),
+            engtype: Some("3D".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            pid_and_luid.instance_pattern(),
+            "pid_1234_luid_0x00000000_0x0000C2D3_*engtype_3D"
+        );
+        assert_eq!(
+            pid_and_luid.counter_path(),
+            r#"\GPU Engine(pid_1234_luid_0x00000000_0x0000C2D3_*engtype_3D)\Utilization Percentage"#
+        );
+
+        // The engine index is dropped after the missing phys, but still
+        // filtered by `matches`
+        let eng_without_phys = GpuEngineQuery {
+            pid: Some(1234),
+            eng: Some(3),
+            engtype: Some("3D".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(eng_without_phys.instance_pattern(), "pid_1234_*engtype_3D");
+
+        let everything = GpuEngineQuery {
+            pid: Some(1234),
+            luid: Some(luid(0, 0xC2D3)),
+            phys: Some(0),
+            eng: Some(3),
+            engtype: Some("3D".to_owned()),
+        };
+        assert_eq!(everything.instance_pattern(), INSTANCE);
+        assert_eq!(GpuEngineQuery::default().instance_pattern(), "*");
+    }
+
+    #[test]
+    fn queries_match_on_every_given_field() {
+        let instance: GpuEngineInstance = INSTANCE.parse().unwrap();
+        let query = GpuEngineQuery {
+            pid: Some(1234),
+            eng: Some(3),
+            ..Default::default()
+        };
+        assert!(query.matches(&instance));
+        assert!(GpuEngineQuery::default().matches(&instance));
+        assert!(
+            !GpuEngineQuery {
+                eng: Some(2),
+                ..query.clone()
+            }
+            .matches(&instance)
+        );
+        assert!(
+            !GpuEngineQuery {
+                luid: Some(luid(-1, 0xC2D3)),
+                ..query.clone()
+            }
+            .matches(&instance)
+        );
+        assert!(
+            !GpuEngineQuery {
+                engtype: Some("Copy".to_owned()),
+                ..query
+            }
+            .matches(&instance)
+        );
+    }
+}
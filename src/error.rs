@@ -0,0 +1,134 @@
+use std::fmt::Display;
+
+use crate::{monitor_selector::MonitorSelectionError, scenario::ScenarioError};
+
+/// The underlying error of a `WgcPerfError`, e.g. a `windows::core::Error`.
+pub type ErrorSource = Box<dyn std::error::Error + Send + Sync>;
+
+// Clap uses 2 for usage errors and thresholds use 3
+pub const CONFIG_EXIT_CODE: u8 = 4;
+pub const IO_EXIT_CODE: u8 = 5;
+pub const COUNTER_EXIT_CODE: u8 = 6;
+pub const CAPTURE_EXIT_CODE: u8 = 7;
+pub const DEVICE_EXIT_CODE: u8 = 8;
+
+/// An error that stops a command, with what we were doing at the time.
+#[derive(Debug)]
+pub enum WgcPerfError {
+    /// The command line, a scenario or thresholds file, or a selected
+    /// monitor or process is invalid.
+    Config {
+        context: String,
+        source: Option<ErrorSource>,
+    },
+    /// A file couldn't be read or written.
+    Io {
+        context: String,
+        source: Option<ErrorSource>,
+    },
+    /// A performance counter couldn't be added or read.
+    Counter {
+        context: String,
+        source: Option<ErrorSource>,
+    },
+    /// WGC or DDA failed to capture.
+    Capture {
+        context: String,
+        source: Option<ErrorSource>,
+    },
+    /// The monitor, adapter, D3D device or test window couldn't be set up.
+    Device {
+        context: String,
+        source: Option<ErrorSource>,
+    },
+}
+
+impl WgcPerfError {
+    pub fn config(context: impl Into<String>) -> Self {
+        WgcPerfError::Config {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    pub fn io(context: impl Into<String>, source: impl Into<ErrorSource>) -> Self {
+        WgcPerfError::Io {
+            context: context.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    pub fn counter(context: impl Into<String>, source: impl Into<ErrorSource>) -> Self {
+        WgcPerfError::Counter {
+            context: context.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    pub fn capture(context: impl Into<String>, source: impl Into<ErrorSource>) -> Self {
+        WgcPerfError::Capture {
+            context: context.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    pub fn device(context: impl Into<String>, source: impl Into<ErrorSource>) -> Self {
+        WgcPerfError::Device {
+            context: context.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    /// The process exit code for the error, one per variant.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            WgcPerfError::Config { .. } => CONFIG_EXIT_CODE,
+            WgcPerfError::Io { .. } => IO_EXIT_CODE,
+            WgcPerfError::Counter { .. } => COUNTER_EXIT_CODE,
+            WgcPerfError::Capture { .. } => CAPTURE_EXIT_CODE,
+            WgcPerfError::Device { .. } => DEVICE_EXIT_CODE,
+        }
+    }
+
+    fn parts(&self) -> (&str, Option<&ErrorSource>) {
+        match self {
+            WgcPerfError::Config { context, source }
+            | WgcPerfError::Io { context, source }
+            | WgcPerfError::Counter { context, source }
+            | WgcPerfError::Capture { context, source }
+            | WgcPerfError::Device { context, source } => (context, source.as_ref()),
+        }
+    }
+}
+
+impl Display for WgcPerfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.parts() {
+            (context, Some(source)) => write!(f, "{}: {}", context, source),
+            (context, None) => write!(f, "{}", context),
+        }
+    }
+}
+
+impl std::error::Error for WgcPerfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.parts()
+            .1
+            .map(|source| source.as_ref() as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl From<ScenarioError> for WgcPerfError {
+    fn from(value: ScenarioError) -> Self {
+        match value {
+            ScenarioError::Io(error) => WgcPerfError::io("Could not read the scenario", error),
+            error => WgcPerfError::config(error.to_string()),
+        }
+    }
+}
+
+impl From<MonitorSelectionError> for WgcPerfError {
+    fn from(value: MonitorSelectionError) -> Self {
+        WgcPerfError::config(value.to_string())
+    }
+}
@@ -0,0 +1,39 @@
+pub mod adapter;
+pub mod aggregate;
+pub mod compare;
+pub mod compositor;
+pub mod csv;
+pub mod drm;
+pub mod engine;
+pub mod error;
+pub mod memory;
+pub mod monitor_selector;
+pub mod plan;
+pub mod process;
+pub mod results;
+pub mod runner;
+pub mod sampling;
+pub mod scenario;
+pub mod significance;
+pub mod sinks;
+pub mod stats;
+pub mod thresholds;
+pub mod typeperf;
+
+#[cfg(test)]
+mod test_support;
+
+#[cfg(windows)]
+pub mod monitor;
+#[cfg(windows)]
+pub mod pdh;
+#[cfg(windows)]
+pub mod perf;
+#[cfg(windows)]
+pub mod perf_session;
+#[cfg(windows)]
+pub mod pid;
+#[cfg(windows)]
+pub mod window;
+#[cfg(windows)]
+pub mod windows_utils;
@@ -1,310 +1,233 @@
-mod adapter;
 mod cli;
-mod monitor;
-mod pdh;
-mod perf;
-mod perf_session;
-mod pid;
-mod sinks;
-mod window;
-mod windows_utils;
+#[cfg(windows)]
+mod record;
 
-use std::{sync::mpsc::channel, time::Duration};
+use std::{path::Path, process::ExitCode};
 
-use adapter::Adapter;
 use clap::Parser;
-use cli::Cli;
-use monitor::Monitor;
-use perf_session::PerfSession;
-use pid::get_current_dwm_pid;
-use sinks::{CaptureSink, dda::DdaCaptureSink, wgc::WgcCaptureSink};
-use window::Window;
-use windows::{
-    System::{DispatcherQueue, DispatcherQueueController, DispatcherQueueHandler},
-    UI::{
-        Color,
-        Composition::{AnimationIterationBehavior, Core::CompositorController},
+use cli::{AnalyzeArgs, Cli, Command, CompareArgs, ReportArgs, RunArgs};
+use wgcperf::{
+    aggregate::{print_summary, summarize_passes},
+    compare::{compare_results, print_results_comparison},
+    csv,
+    error::WgcPerfError,
+    plan::PassOrder,
+    process::Process,
+    results::{PassResult, RunConfig, RunResults},
+    scenario::{PassSpec, Scenario, WorkloadSettings},
+    stats::SampleStats,
+    thresholds::{
+        Evaluation, THRESHOLD_FAILURE_EXIT_CODE, evaluate_comparison, evaluate_run,
+        print_evaluation,
     },
-    Win32::{
-        Graphics::{
-            Dxgi::{CreateDXGIFactory1, IDXGIAdapter1, IDXGIFactory1, IDXGIOutput1},
-            Gdi::{GetMonitorInfoW, MONITORINFO},
-        },
-        System::{
-            WinRT::{RO_INIT_MULTITHREADED, RoInitialize},
-            WindowsProgramming::MulDiv,
-        },
-        UI::HiDpi::{
-            DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, GetDpiForMonitor, MDT_EFFECTIVE_DPI,
-            SetProcessDpiAwarenessContext,
-        },
-    },
-    core::{Interface, Result, h},
-};
-use windows_numerics::{Vector2, Vector3};
-use windows_utils::{
-    composition::CompositionInterop,
-    d3d::create_d3d_device_on_adapter,
-    dxgi::{DxgiAdapterIter, DxgiOutputIter},
+    typeperf::{CounterLog, CounterLogError},
 };
 
-fn main() -> Result<()> {
-    // Cli
+fn main() -> ExitCode {
     let args = Cli::parse();
-    let monitor_index = args.monitor;
-    let test_duration = Duration::from_millis(args.duration);
-    let rest_duration = Duration::from_millis(args.rest);
-    let use_dirty_rects = args.use_dirty_rects;
-    let verbose = args.verbose;
-    let adhoc_mode = args.adhoc;
-
-    unsafe {
-        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
+    let result = match args.command {
+        Command::Run(run_args) => run(run_args),
+        Command::Compare(compare_args) => compare(compare_args),
+        Command::Report(report_args) => report(report_args),
+        Command::Analyze(analyze_args) => analyze(analyze_args),
+        #[cfg(windows)]
+        Command::Adhoc(adhoc_args) => record::adhoc(adhoc_args).map(|_| None),
+        #[cfg(windows)]
+        Command::ListMonitors => record::list_monitors().map(|_| None),
+        #[cfg(windows)]
+        Command::ListAdapters => record::list_adapters().map(|_| None),
+        #[cfg(not(windows))]
+        _ => Err(windows_only()),
+    };
+    match result {
+        Ok(Some(evaluation)) if !evaluation.passed() => ExitCode::from(THRESHOLD_FAILURE_EXIT_CODE),
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::from(error.exit_code())
+        }
     }
-    unsafe { RoInitialize(RO_INIT_MULTITHREADED)? };
+}
 
-    let monitors = Monitor::enumerate_all()?;
-    let monitor = monitors
-        .get(monitor_index)
-        .expect("Provided monitor index is out of bounds!");
-    let monitor_handle = monitor.handle();
-    let monitor_info = unsafe {
-        let mut info = MONITORINFO::default();
-        info.cbSize = std::mem::size_of_val(&info) as u32;
-        GetMonitorInfoW(monitor_handle, &mut info).ok()?;
-        info
-    };
-    let work_area = monitor_info.rcWork;
-    println!("Monitor details:");
-    println!("  index: {}", monitor_index);
-    println!("  handle: {:010X}", monitor_handle.0 as usize);
-    println!("  name: {}", monitor.display_name());
-    println!("  frequency: {} Hz", monitor.display_frequency());
-    println!();
+/// Returns the threshold evaluation, if any thresholds were given.
+fn compare(args: CompareArgs) -> Result<Option<Evaluation>, WgcPerfError> {
+    let thresholds = args.gate.load()?;
+    let old = load_results(&args.old)?;
+    let new = load_results(&args.new)?;
+    let comparison = compare_results(&old, &new, args.alpha);
+    print_results_comparison(&comparison);
 
-    // Compute window position
-    let dpi = unsafe {
-        let mut dpix = 0;
-        let mut dpiy = 0;
-        GetDpiForMonitor(monitor_handle, MDT_EFFECTIVE_DPI, &mut dpix, &mut dpiy)?;
-        assert_eq!(dpix, dpiy);
-        dpix
-    };
-    let window_width = unsafe { MulDiv(500, dpi as i32, 96) };
-    let window_height = unsafe { MulDiv(500, dpi as i32, 96) };
-    let work_area_width = work_area.right - work_area.left;
-    let work_area_height = work_area.bottom - work_area.top;
-    let window_x = ((work_area_width - window_width) / 2) + work_area.left;
-    let window_y = ((work_area_height - window_height) / 2) + work_area.top;
+    if thresholds.is_empty() {
+        return Ok(None);
+    }
+    let evaluation = evaluate_comparison(&thresholds, &new, &comparison);
+    print_evaluation(&evaluation);
+    Ok(Some(evaluation))
+}
 
-    // Create the UI thread
-    let ui_thread = DispatcherQueueController::CreateOnDedicatedThread()?;
-    let ui_queue = ui_thread.DispatcherQueue()?;
+/// Returns the threshold evaluation, if any thresholds were given.
+fn report(args: ReportArgs) -> Result<Option<Evaluation>, WgcPerfError> {
+    let thresholds = args.gate.load()?;
+    let results = load_results(&args.results)?;
+    if let Some(monitor) = &results.monitor {
+        println!(
+            "Monitor: {} ({}) - {} Hz",
+            monitor.name, monitor.device_name, monitor.frequency
+        );
+    }
+    if let Some(name) = &results.config.scenario.name {
+        println!("Scenario: {}", name);
+    }
+    println!();
+    print_summary(&results.adapters, &results.processes, &results.summary);
+
+    if let Some(csv_path) = &args.csv {
+        csv::save_samples_csv(
+            csv_path,
+            &results.adapters,
+            &results.processes,
+            &results.passes,
+        )
+        .map_err(|error| save_error(csv_path, error))?;
+        println!("Saved samples to \"{}\"", csv_path.display());
+    }
 
-    // Create our dummy window
-    let window = {
-        let (sender, receiver) = channel();
-        ui_queue.TryEnqueue(&DispatcherQueueHandler::new(move || -> Result<()> {
-            let result = Window::new(
-                "Dummy Content",
-                window_x,
-                window_y,
-                window_width as u32,
-                window_height as u32,
-            );
-            sender.send(result).unwrap();
-            Ok(())
-        }))?;
-        let window = receiver.recv().unwrap()?;
-        window
-    };
+    if thresholds.is_empty() {
+        return Ok(None);
+    }
+    let evaluation = evaluate_run(&thresholds, &results);
+    print_evaluation(&evaluation);
+    Ok(Some(evaluation))
+}
 
-    // Create our dummy content
-    let compositor_controller = {
-        let (sender, receiver) = channel();
-        ui_queue.TryEnqueue(&DispatcherQueueHandler::new(move || -> Result<()> {
-            let result = CompositorController::new();
-            sender.send(result).unwrap();
-            Ok(())
-        }))?;
-        let compositor_controller = receiver.recv().unwrap()?;
-        compositor_controller
-    };
-    let compositor = compositor_controller.Compositor()?;
-    let root = compositor.CreateSpriteVisual()?;
-    root.SetRelativeSizeAdjustment(Vector2::new(1.0, 1.0))?;
-    root.SetBrush(&compositor.CreateColorBrushWithColor(Color {
-        A: 255,
-        R: 0,
-        G: 0,
-        B: 0,
-    })?)?;
-    let content = compositor.CreateSpriteVisual()?;
-    content.SetRelativeSizeAdjustment(Vector2 { X: 0.33, Y: 0.33 })?;
-    content.SetAnchorPoint(Vector2 { X: 0.5, Y: 0.5 })?;
-    content.SetRelativeOffsetAdjustment(Vector3 {
-        X: 0.5,
-        Y: 0.5,
-        Z: 0.0,
+/// Returns the threshold evaluation, if any thresholds were given.
+fn analyze(args: AnalyzeArgs) -> Result<Option<Evaluation>, WgcPerfError> {
+    let thresholds = args.gate.load()?;
+    let log = CounterLog::load(&args.from_csv).map_err(|error| match error {
+        CounterLogError::Io(error) => WgcPerfError::io(
+            format!("Could not read \"{}\"", args.from_csv.display()),
+            error,
+        ),
+        error => WgcPerfError::config(format!(
+            "Could not parse \"{}\": {}",
+            args.from_csv.display(),
+            error
+        )),
     })?;
-    content.SetBrush(&compositor.CreateColorBrushWithColor(Color {
-        A: 255,
-        R: 255,
-        G: 0,
-        B: 0,
-    })?)?;
-    root.Children()?.InsertAtTop(&content)?;
-    let target = compositor.create_desktop_window_target(window.handle(), false)?;
-    target.SetRoot(&root)?;
-
-    // Animate the content
-    let easing = compositor.CreateLinearEasingFunction()?;
-    let animation = compositor.CreateScalarKeyFrameAnimation()?;
-    animation.InsertKeyFrameWithEasingFunction(0.0, 0.0, &easing)?;
-    animation.InsertKeyFrameWithEasingFunction(1.0, 360.0, &easing)?;
-    animation.SetDuration(Duration::from_secs(3).into())?;
-    animation.SetIterationBehavior(AnimationIterationBehavior::Forever)?;
-    content.StartAnimation(h!("RotationAngleInDegrees"), &animation)?;
-    compositor_controller.Commit()?;
-
-    // Show the window
-    window.show();
-
-    // Initialize D3D
-    let dxgi_factory: IDXGIFactory1 = unsafe { CreateDXGIFactory1()? };
-    let dxgi_adapters: Vec<IDXGIAdapter1> = dxgi_factory.iter_adapters().collect();
-    let (adapter, output) = dxgi_adapters
-        .iter()
-        .find_map(|adapter| {
-            if let Some(output) = adapter.iter_outputs().find(|output| {
-                if let Ok(desc) = unsafe { output.GetDesc() } {
-                    desc.Monitor == monitor_handle
-                } else {
-                    false
-                }
-            }) {
-                Some((adapter.clone(), output))
-            } else {
-                None
-            }
-        })
-        .expect("Couldn't find the adapter for the given monitor!");
-    let d3d_device = create_d3d_device_on_adapter(&adapter)?;
-
-    // Get the DWM's pid
-    let pid = get_current_dwm_pid()?;
+    let engines = args.engines.0;
+    let log_samples = log.gpu_engine_samples(args.pid, &engines);
+    if log_samples.series.is_empty() {
+        return Err(WgcPerfError::config(format!(
+            "\"{}\" has no GPU Engine utilization counters for pid {}",
+            args.from_csv.display(),
+            args.pid
+        )));
+    }
 
-    // Collect all adapters
-    let adapters = {
-        let mut adapters = Vec::with_capacity(dxgi_adapters.len());
-        for dxgi_adapter in &dxgi_adapters {
-            adapters.push(Adapter::from_dxgi_adapter(&dxgi_adapter)?);
-        }
-        adapters
+    // The log is treated as a single pass
+    let pass = PassSpec::new(args.kind, log_samples.duration_ms, 0);
+    let samples = log_samples
+        .samples
+        .into_iter()
+        .map(|x| (SampleStats::from_timed_samples(&x.samples), x))
+        .collect();
+    let passes = vec![PassResult::new(
+        &pass,
+        0,
+        None,
+        &log_samples.series,
+        samples,
+    )];
+    // There is no baseline to compare a single pass against
+    let alpha = 0.05;
+    let summary = summarize_passes(&passes, alpha);
+    let processes = vec![Process {
+        name: args.process_name,
+        pid: args.pid,
+    }];
+    print_summary(&log_samples.adapters, &processes, &summary);
+
+    if let Some(csv_path) = &args.csv {
+        csv::save_samples_csv(csv_path, &log_samples.adapters, &processes, &passes)
+            .map_err(|error| save_error(csv_path, error))?;
+        println!("Saved samples to \"{}\"", csv_path.display());
+    }
+    let run_config = RunConfig {
+        monitor: None,
+        seed: 0,
+        alpha,
+        engines,
+        memory: false,
+        cpu: false,
+        scenario: Scenario {
+            name: None,
+            iterations: 1,
+            order: PassOrder::Fixed,
+            seed: None,
+            sample_interval_ms: log_samples.sample_interval_ms,
+            workload: WorkloadSettings::default(),
+            passes: vec![pass],
+        },
     };
-    println!("Adapters:");
-    for (i, adapter) in adapters.iter().enumerate() {
-        println!("  {} - {}", i, adapter.name);
+    let results = RunResults::new(
+        run_config,
+        None,
+        log_samples.adapters,
+        processes,
+        passes,
+        summary,
+    );
+    if let Some(output_path) = &args.output {
+        results
+            .save(output_path)
+            .map_err(|error| save_error(output_path, error))?;
+        println!("Saved results to \"{}\"", output_path.display());
     }
-    println!();
 
-    if !adhoc_mode {
-        // Run the test as normal
-
-        // Record baseline
-        println!("Recording baseline...");
-        let baseline_samples = run_test(&ui_queue, test_duration, pid, &adapters, verbose)?;
-        print_averages(&adapters, &baseline_samples);
-        println!();
-
-        // Record WGC
-        println!("Recording WGC...");
-        let mut wgc_sink = WgcCaptureSink::new(&d3d_device, monitor_handle, use_dirty_rects)?;
-        let _wgc_samples = run_and_print_test(
-            &mut wgc_sink,
-            &ui_queue,
-            test_duration,
-            rest_duration,
-            pid,
-            &adapters,
-            verbose,
-        )?;
+    if thresholds.is_empty() {
+        return Ok(None);
+    }
+    let evaluation = evaluate_run(&thresholds, &results);
+    print_evaluation(&evaluation);
+    Ok(Some(evaluation))
+}
 
-        // Record DDA
-        println!("Recording DDA...");
-        let output: IDXGIOutput1 = output.cast()?;
-        let mut dda_sink = DdaCaptureSink::new(&d3d_device, output)?;
-        let _dda_samples = run_and_print_test(
-            &mut dda_sink,
-            &ui_queue,
-            test_duration,
-            rest_duration,
-            pid,
-            &adapters,
-            verbose,
-        )?;
-    } else {
-        // Run WGC until the user says stop
+/// Returns the threshold evaluation, if any thresholds were given.
+fn run(args: RunArgs) -> Result<Option<Evaluation>, WgcPerfError> {
+    // Load the thresholds and scenario up front so that a typo doesn't
+    // waste a run
+    let thresholds = args.gate.load()?;
+    let scenario = args.scenario()?;
+    let results = record_passes(args, scenario)?;
 
-        let mut wgc_sink = WgcCaptureSink::new(&d3d_device, monitor_handle, use_dirty_rects)?;
-        wgc_sink.start()?;
-        println!("Press ENTER to stop...");
-        std::io::Read::read(&mut std::io::stdin(), &mut [0]).unwrap();
-        wgc_sink.stop()?;
+    if thresholds.is_empty() {
+        return Ok(None);
     }
+    let evaluation = evaluate_run(&thresholds, &results);
+    print_evaluation(&evaluation);
+    Ok(Some(evaluation))
+}
 
-    // Shut down the UI thread and the window
-    window.close();
-    ui_thread.ShutdownQueueAsync()?.get()?;
-
-    // TODO: Save samples
+fn load_results(path: &Path) -> Result<RunResults, WgcPerfError> {
+    RunResults::load(path)
+        .map_err(|error| WgcPerfError::io(format!("Could not load \"{}\"", path.display()), error))
+}
 
-    Ok(())
+fn save_error(path: &Path, error: std::io::Error) -> WgcPerfError {
+    WgcPerfError::io(format!("Could not save \"{}\"", path.display()), error)
 }
 
-fn run_test(
-    thread: &DispatcherQueue,
-    duration: Duration,
-    pid: u32,
-    adapters: &[Adapter],
-    verbose: bool,
-) -> Result<Vec<(f64, Vec<f64>)>> {
-    let mut result = Vec::with_capacity(adapters.len());
-    let samples = PerfSession::run_on_thread(&thread, duration, pid, &adapters, verbose)?;
-    for samples in samples {
-        let average = if !samples.is_empty() {
-            let sum: f64 = samples.iter().sum();
-            sum / samples.len() as f64
-        } else {
-            0.0
-        };
-        result.push((average, samples));
-    }
-    Ok(result)
+#[cfg(windows)]
+fn record_passes(args: RunArgs, scenario: Scenario) -> Result<RunResults, WgcPerfError> {
+    record::run(args, scenario)
 }
 
-fn print_averages(adapters: &[Adapter], adapter_samples: &[(f64, Vec<f64>)]) {
-    println!("Average GPU 3D engine utilization by adapter:");
-    for (i, (adapter, (utilization, _))) in adapters.iter().zip(adapter_samples).enumerate() {
-        println!("  {} - {:6.2}% - {}", i, utilization, adapter.name);
-    }
+#[cfg(not(windows))]
+fn record_passes(_args: RunArgs, _scenario: Scenario) -> Result<RunResults, WgcPerfError> {
+    Err(windows_only())
 }
 
-fn run_and_print_test<C: CaptureSink>(
-    sink: &mut C,
-    thread: &DispatcherQueue,
-    test_duration: Duration,
-    rest_duration: Duration,
-    pid: u32,
-    adapters: &[Adapter],
-    verbose: bool,
-) -> Result<Vec<(f64, Vec<f64>)>> {
-    sink.start()?;
-    let samples = run_test(&thread, test_duration, pid, &adapters, verbose)?;
-    let num_frames = sink.stop()?;
-    println!("Captured {} frames", num_frames);
-    print_averages(&adapters, &samples);
-    println!();
-    std::thread::sleep(rest_duration);
-    Ok(samples)
+#[cfg(not(windows))]
+fn windows_only() -> WgcPerfError {
+    WgcPerfError::config("This command is only supported on Windows.")
 }
@@ -0,0 +1,120 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{adapter::Luid, engine::counter_path_instance};
+
+/// The instance name of a GPU Process Memory counter, e.g.
+/// `pid_1234_luid_0x00000000_0x0000C2D3_phys_0`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GpuMemoryInstance {
+    pub pid: u32,
+    pub luid: Luid,
+    /// The physical adapter within a linked adapter.
+    pub phys: u32,
+}
+
+impl GpuMemoryInstance {
+    pub fn from_counter_path(path: &str) -> Result<Self, String> {
+        counter_path_instance(path)?.parse()
+    }
+}
+
+impl FromStr for GpuMemoryInstance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("\"{}\" is not a GPU memory instance", s);
+        let rest = s.strip_prefix("pid_").ok_or_else(invalid)?;
+        let (pid, rest) = rest.split_once("_luid_").ok_or_else(invalid)?;
+        let (luid, phys) = rest.split_once("_phys_").ok_or_else(invalid)?;
+        Ok(Self {
+            pid: pid.parse().map_err(|_| invalid())?,
+            luid: luid.parse()?,
+            phys: phys.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Display for GpuMemoryInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "pid_{}_luid_{}_phys_{}", self.pid, self.luid, self.phys)
+    }
+}
+
+/// The wildcard path for a GPU Process Memory counter (e.g.
+/// `Dedicated Usage`) of a process on one adapter. The counters are in
+/// bytes.
+pub fn gpu_memory_counter_path(pid: u32, luid: Luid, counter: &str) -> String {
+    format!(
+        r#"\GPU Process Memory(pid_{}_luid_{}_phys_*)\{}"#,
+        pid, luid, counter
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSTANCE: &str = "pid_1234_luid_0x00000000_0x0000C2D3_phys_0";
+
+    #[test]
+    fn instances_round_trip() {
+        let instance: GpuMemoryInstance = INSTANCE.parse().unwrap();
+        assert_eq!(
+            instance,
+            GpuMemoryInstance {
+                pid: 1234,
+                luid: Luid {
+                    high_part: 0,
+                    low_part: 0xC2D3,
+                },
+                phys: 0,
+            }
+        );
+        assert_eq!(instance.to_string(), INSTANCE);
+
+        let path = format!(
+            r#"\\MACHINE\GPU Process Memory({})\Dedicated Usage"#,
+            INSTANCE
+        );
+        assert_eq!(GpuMemoryInstance::from_counter_path(&path), Ok(instance));
+    }
+
+    #[test]
+    fn negative_high_parts_round_trip() {
+        let text = "pid_8_luid_0xFFFFFFFF_0x00010A2B_phys_1";
+        let instance: GpuMemoryInstance = text.parse().unwrap();
+        assert_eq!(instance.luid.high_part, -1);
+        assert_eq!(instance.phys, 1);
+        assert_eq!(instance.to_string(), text);
+    }
+
+    #[test]
+    fn rejects_malformed_instances() {
+        for text in [
+            "",
+            "pid_1234",
+            "pid_x_luid_0x00000000_0x0000C2D3_phys_0",
+            "pid_1234_luid_00000000_0000C2D3_phys_0",
+            "pid_1234_luid_0x00000000_0x0000C2D3_phys_",
+            // A GPU Engine instance
+            "pid_1234_luid_0x00000000_0x0000C2D3_phys_0_eng_0_engtype_3D",
+        ] {
+            assert!(text.parse::<GpuMemoryInstance>().is_err(), "{}", text);
+        }
+        assert!(
+            GpuMemoryInstance::from_counter_path(r"\GPU Process Memory\Dedicated Usage").is_err()
+        );
+    }
+
+    #[test]
+    fn builds_wildcard_paths() {
+        let luid = Luid {
+            high_part: 0,
+            low_part: 0xC2D3,
+        };
+        assert_eq!(
+            gpu_memory_counter_path(1234, luid, "Shared Usage"),
+            r#"\GPU Process Memory(pid_1234_luid_0x00000000_0x0000C2D3_phys_*)\Shared Usage"#
+        );
+    }
+}
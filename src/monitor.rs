@@ -14,10 +14,13 @@ use windows::{
             DEVMODEW, ENUM_CURRENT_SETTINGS, EnumDisplayMonitors, EnumDisplaySettingsW,
             GetMonitorInfoW, HDC, HMONITOR, MONITORINFOEXW,
         },
+        UI::WindowsAndMessaging::MONITORINFOF_PRIMARY,
     },
     core::{BOOL, PCWSTR, Result},
 };
 
+use crate::monitor_selector::MonitorDescriptor;
+
 fn get_display_config_path_infos() -> Result<Vec<DISPLAYCONFIG_PATH_INFO>> {
     let mut num_paths = 0;
     let mut num_modes = 0;
@@ -116,9 +119,11 @@ unsafe extern "system" fn enum_display_monitors(
 }
 
 pub struct Monitor {
-    _device_name: String,
+    device_name: String,
     display_name: String,
     display_frequency: u32,
+    bounds: RECT,
+    is_primary: bool,
     handle: HMONITOR,
 }
 
@@ -142,10 +147,12 @@ impl Monitor {
                 .unwrap_or(monitor_info.szDevice.len());
             let device_name = String::from_utf16(&monitor_info.szDevice[..len])?;
 
+            // Fall back to the device name for monitors without a display
+            // config path, e.g. ones that were just disconnected
             let display_name = device_name_to_display_name
                 .get(&device_name)
-                .unwrap()
-                .clone();
+                .cloned()
+                .unwrap_or_else(|| device_name.clone());
 
             let mut dev_mode = DEVMODEW::default();
             unsafe {
@@ -159,9 +166,11 @@ impl Monitor {
             let display_frequency = dev_mode.dmDisplayFrequency;
 
             monitors.push(Monitor {
-                _device_name: device_name,
+                device_name,
                 display_name,
                 display_frequency,
+                bounds: monitor_info.monitorInfo.rcMonitor,
+                is_primary: monitor_info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0,
                 handle,
             });
         }
@@ -177,7 +186,29 @@ impl Monitor {
         self.display_frequency
     }
 
+    /// The monitor's rect in virtual screen coordinates.
+    pub fn bounds(&self) -> RECT {
+        self.bounds
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.is_primary
+    }
+
+    pub fn descriptor(&self, index: usize) -> MonitorDescriptor {
+        MonitorDescriptor {
+            index,
+            name: self.display_name.clone(),
+            device_name: self.device_name.clone(),
+            is_primary: self.is_primary,
+        }
+    }
+
     pub fn display_name(&self) -> &str {
         &self.display_name
     }
+
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
 }
@@ -0,0 +1,209 @@
+use std::{fmt::Display, str::FromStr};
+
+/// The parts of a monitor that can be used to select it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MonitorDescriptor {
+    pub index: usize,
+    /// The friendly name, e.g. `DELL U2720Q`.
+    pub name: String,
+    /// The GDI device name, e.g. `\\.\DISPLAY2`.
+    pub device_name: String,
+    pub is_primary: bool,
+}
+
+/// How the user asked for a monitor. Indices follow the enumeration order,
+/// which isn't stable across reboots or docking, so names and device names
+/// are usually the better choice in scripts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MonitorSelector {
+    Index(usize),
+    Primary,
+    DeviceName(String),
+    Name(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MonitorSelectionError {
+    NoMatch(MonitorSelector),
+    /// The selector matched more than one monitor, which are listed.
+    Ambiguous(MonitorSelector, Vec<MonitorDescriptor>),
+}
+
+impl FromStr for MonitorSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            Err("The monitor can't be empty".to_owned())
+        } else if let Ok(index) = s.parse() {
+            Ok(MonitorSelector::Index(index))
+        } else if s.eq_ignore_ascii_case("primary") {
+            Ok(MonitorSelector::Primary)
+        } else if s.starts_with(r"\\.\") {
+            Ok(MonitorSelector::DeviceName(s.to_owned()))
+        } else {
+            Ok(MonitorSelector::Name(s.to_owned()))
+        }
+    }
+}
+
+impl Display for MonitorSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MonitorSelector::Index(index) => write!(f, "{}", index),
+            MonitorSelector::Primary => write!(f, "primary"),
+            MonitorSelector::DeviceName(name) | MonitorSelector::Name(name) => {
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+impl Display for MonitorSelectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MonitorSelectionError::NoMatch(selector) => write!(
+                f,
+                "No monitor matches \"{}\". Run list-monitors to see the available monitors.",
+                selector
+            ),
+            MonitorSelectionError::Ambiguous(selector, monitors) => {
+                write!(f, "\"{}\" matches more than one monitor:", selector)?;
+                for monitor in monitors {
+                    write!(
+                        f,
+                        " {} - {} ({});",
+                        monitor.index, monitor.name, monitor.device_name
+                    )?;
+                }
+                write!(f, " use the index or device name instead.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MonitorSelectionError {}
+
+/// Finds the monitor the selector refers to and returns its index. Names
+/// are compared case-insensitively, and a name that doesn't match any
+/// monitor exactly may match part of a single monitor's name.
+pub fn select_monitor(
+    selector: &MonitorSelector,
+    monitors: &[MonitorDescriptor],
+) -> Result<usize, MonitorSelectionError> {
+    let matches: Vec<_> = match selector {
+        MonitorSelector::Index(index) => monitors.iter().filter(|x| x.index == *index).collect(),
+        MonitorSelector::Primary => monitors.iter().filter(|x| x.is_primary).collect(),
+        MonitorSelector::DeviceName(device_name) => monitors
+            .iter()
+            .filter(|x| x.device_name.eq_ignore_ascii_case(device_name))
+            .collect(),
+        MonitorSelector::Name(name) => {
+            let exact: Vec<_> = monitors
+                .iter()
+                .filter(|x| x.name.eq_ignore_ascii_case(name))
+                .collect();
+            if !exact.is_empty() {
+                exact
+            } else {
+                let name = name.to_lowercase();
+                monitors
+                    .iter()
+                    .filter(|x| x.name.to_lowercase().contains(&name))
+                    .collect()
+            }
+        }
+    };
+    match matches.as_slice() {
+        [] => Err(MonitorSelectionError::NoMatch(selector.clone())),
+        [monitor] => Ok(monitor.index),
+        _ => Err(MonitorSelectionError::Ambiguous(
+            selector.clone(),
+            matches.into_iter().cloned().collect(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitors() -> Vec<MonitorDescriptor> {
+        [
+            ("DELL U2720Q", r"\\.\DISPLAY1", false),
+            ("DELL P2419H", r"\\.\DISPLAY2", true),
+            ("LG HDR 4K", r"\\.\DISPLAY3", false),
+            ("LG HDR 4K Ultra", r"\\.\DISPLAY4", false),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(
+            |(index, (name, device_name, is_primary))| MonitorDescriptor {
+                index,
+                name: name.to_owned(),
+                device_name: device_name.to_owned(),
+                is_primary,
+            },
+        )
+        .collect()
+    }
+
+    fn select(selector: &str) -> Result<usize, MonitorSelectionError> {
+        select_monitor(&selector.parse().unwrap(), &monitors())
+    }
+
+    #[test]
+    fn parses_each_kind_of_selector() {
+        assert_eq!("2".parse(), Ok(MonitorSelector::Index(2)));
+        assert_eq!("Primary".parse(), Ok(MonitorSelector::Primary));
+        assert_eq!(
+            r"\\.\DISPLAY2".parse(),
+            Ok(MonitorSelector::DeviceName(r"\\.\DISPLAY2".to_owned()))
+        );
+        assert_eq!(
+            " DELL U2720Q ".parse(),
+            Ok(MonitorSelector::Name("DELL U2720Q".to_owned()))
+        );
+        assert!("".parse::<MonitorSelector>().is_err());
+    }
+
+    #[test]
+    fn selects_by_index_primary_and_device_name() {
+        assert_eq!(select("3"), Ok(3));
+        assert_eq!(select("primary"), Ok(1));
+        assert_eq!(select(r"\\.\display3"), Ok(2));
+    }
+
+    #[test]
+    fn exact_name_wins_over_substring() {
+        // "LG HDR 4K" is also part of "LG HDR 4K Ultra"
+        assert_eq!(select("lg hdr 4k"), Ok(2));
+        assert_eq!(select("ultra"), Ok(3));
+    }
+
+    #[test]
+    fn ambiguous_name_lists_the_matches() {
+        let Err(MonitorSelectionError::Ambiguous(_, matches)) = select("dell") else {
+            panic!("Expected an ambiguous selection");
+        };
+        let indices: Vec<_> = matches.iter().map(|x| x.index).collect();
+        assert_eq!(indices, [0, 1]);
+    }
+
+    #[test]
+    fn unknown_monitor_is_not_found() {
+        assert_eq!(
+            select("9"),
+            Err(MonitorSelectionError::NoMatch(MonitorSelector::Index(9)))
+        );
+        assert!(matches!(
+            select(r"\\.\DISPLAY9"),
+            Err(MonitorSelectionError::NoMatch(_))
+        ));
+        assert!(matches!(
+            select("Samsung"),
+            Err(MonitorSelectionError::NoMatch(_))
+        ));
+    }
+}
@@ -165,6 +165,7 @@ impl PerfQueryHandle {
             unsafe {
                 PDH_FUNCTION(PdhCloseQuery(self.0)).ok()?;
             }
+            self.0 = PDH_HQUERY::default();
         }
         Ok(())
     }
@@ -176,12 +177,13 @@ impl Drop for PerfQueryHandle {
     }
 }
 
-pub fn add_perf_counters(
+/// Expands a wildcard counter path into the full path of every matching
+/// counter instance.
+pub fn expand_wildcard_path(
     query_handle: &PerfQueryHandle,
     wildcard_path: &str,
-    verbose: bool,
-) -> Result<Vec<PDH_HCOUNTER>> {
-    let counter_handles: Vec<PDH_HCOUNTER> = unsafe {
+) -> Result<Vec<String>> {
+    unsafe {
         let mut counter_handle = PDH_HCOUNTER::default();
         PDH_FUNCTION(PdhAddEnglishCounterW(
             query_handle.0,
@@ -228,23 +230,24 @@ pub fn add_perf_counters(
         let mut start = 0;
         for (i, char) in buffer.iter().enumerate() {
             if *char == 0 && i != start {
-                let path = HSTRING::from_wide(&buffer[start..i]);
-                paths.push(path);
+                paths.push(String::from_utf16(&buffer[start..i])?);
                 start = i + 1;
             }
         }
+        Ok(paths)
+    }
+}
 
-        let mut counter_handles = Vec::new();
-        for path in &paths {
-            if verbose {
-                println!("Full path: {}", path);
-            }
-            let mut counter_handle = PDH_HCOUNTER::default();
-            PDH_FUNCTION(PdhAddCounterW(query_handle.0, path, 0, &mut counter_handle)).ok()?;
-            counter_handles.push(counter_handle);
-        }
-
-        counter_handles
-    };
-    Ok(counter_handles)
+pub fn add_perf_counter(query_handle: &PerfQueryHandle, path: &str) -> Result<PDH_HCOUNTER> {
+    let mut counter_handle = PDH_HCOUNTER::default();
+    unsafe {
+        PDH_FUNCTION(PdhAddCounterW(
+            query_handle.0,
+            &HSTRING::from(path),
+            0,
+            &mut counter_handle,
+        ))
+        .ok()?;
+    }
+    Ok(counter_handle)
 }
@@ -1,81 +1,197 @@
 use windows::{
     Win32::{
-        Foundation::LUID,
+        Foundation::E_FAIL,
         System::Performance::{
-            PDH_CSTATUS_VALID_DATA, PDH_FMT_COUNTERVALUE, PDH_FMT_DOUBLE, PDH_HCOUNTER,
-            PdhCollectQueryData, PdhGetFormattedCounterValue,
+            PDH_CSTATUS_NEW_DATA, PDH_CSTATUS_VALID_DATA, PDH_FMT, PDH_FMT_COUNTERVALUE,
+            PDH_FMT_DOUBLE, PDH_HCOUNTER, PdhCollectQueryData, PdhGetFormattedCounterValue,
         },
     },
     core::Result,
 };
 
-use crate::pdh::{PDH_FUNCTION, PerfQueryHandle, add_perf_counters};
+use crate::{
+    adapter::Luid,
+    engine::{GpuEngineInstance, GpuEngineQuery, counter_path_instance},
+    memory::{GpuMemoryInstance, gpu_memory_counter_path},
+    pdh::{PDH_FUNCTION, PerfQueryHandle, add_perf_counter, expand_wildcard_path},
+    sampling::UtilizationSource,
+};
 
 pub struct PerfTracker {
     query_handle: PerfQueryHandle,
     counter_handles: Vec<PDH_HCOUNTER>,
+    instances: Vec<String>,
+    instance_values: Vec<f64>,
+    /// Multiplies each counter value, e.g. to convert bytes to MiB.
+    scale: f64,
+    /// How PDH formats each counter value.
+    format: PDH_FMT,
 }
 
 impl PerfTracker {
-    pub fn new(process_id: u32, luid: Option<LUID>, verbose: bool) -> Result<Self> {
-        let counter_path = if let Some(luid) = luid {
-            format!(
-                r#"\GPU Engine(pid_{}_luid_{:#010X}_{:#010X}*engtype_3D)\Utilization Percentage"#,
-                process_id, luid.HighPart, luid.LowPart,
-            )
-        } else {
-            format!(
-                r#"\GPU Engine(pid_{}*engtype_3D)\Utilization Percentage"#,
-                process_id
-            )
+    /// Tracks the utilization of a process's GPU engines of one type.
+    pub fn new(
+        process_id: u32,
+        luid: Option<Luid>,
+        engine_type: &str,
+        verbose: bool,
+    ) -> Result<Self> {
+        let query = GpuEngineQuery {
+            pid: Some(process_id),
+            luid,
+            engtype: Some(engine_type.to_owned()),
+            ..Default::default()
         };
+        // The wildcard can match more than we asked for, e.g. a longer pid
+        Self::from_wildcard_path(
+            &query.counter_path(),
+            |path| GpuEngineInstance::from_counter_path(path).is_ok_and(|x| query.matches(&x)),
+            1.0,
+            PDH_FMT_DOUBLE,
+            verbose,
+        )
+    }
+
+    /// Tracks one GPU Process Memory counter (e.g. `Dedicated Usage`) of a
+    /// process on one adapter, in MiB.
+    pub fn gpu_memory(process_id: u32, luid: Luid, counter: &str, verbose: bool) -> Result<Self> {
+        Self::from_wildcard_path(
+            &gpu_memory_counter_path(process_id, luid, counter),
+            |path| {
+                GpuMemoryInstance::from_counter_path(path)
+                    .is_ok_and(|x| x.pid == process_id && x.luid == luid)
+            },
+            1.0 / (1024.0 * 1024.0),
+            PDH_FMT_DOUBLE,
+            verbose,
+        )
+    }
+
+    /// Tracks the processor time of a process, in percent of every logical
+    /// processor. This uses the `Process V2` counter set, whose instances
+    /// are named `<name>:<pid>`, so the process can't be confused with
+    /// another of the same name.
+    pub fn cpu(process_id: u32, verbose: bool) -> Result<Self> {
+        // Not exported by the windows crate. Processor time can exceed 100%
+        // on multiprocessor systems, so it mustn't be capped.
+        const PDH_FMT_NOCAP100: u32 = 0x8000;
+        let suffix = format!(":{}", process_id);
+        let processors = std::thread::available_parallelism().map_or(1, |x| x.get());
+        Self::from_wildcard_path(
+            &format!(r#"\Process V2(*{})\% Processor Time"#, suffix),
+            |path| counter_path_instance(path).is_ok_and(|x| x.ends_with(&suffix)),
+            1.0 / processors as f64,
+            PDH_FMT(PDH_FMT_DOUBLE.0 | PDH_FMT_NOCAP100),
+            verbose,
+        )
+    }
+
+    /// Adds a counter for every path the wildcard expands to that passes
+    /// `filter`.
+    fn from_wildcard_path<F>(
+        counter_path: &str,
+        filter: F,
+        scale: f64,
+        format: PDH_FMT,
+        verbose: bool,
+    ) -> Result<Self>
+    where
+        F: Fn(&str) -> bool,
+    {
         if verbose {
             println!("Search path: {}", counter_path);
         }
 
         let query_handle = PerfQueryHandle::open_query()?;
-        let counter_handles = add_perf_counters(&query_handle, &counter_path, verbose)?;
+        let mut counter_handles = Vec::new();
+        let mut instances = Vec::new();
+        for path in expand_wildcard_path(&query_handle, counter_path)? {
+            if !filter(&path) {
+                if verbose {
+                    println!("Skipped path: {}", path);
+                }
+                continue;
+            }
+            if verbose {
+                println!("Full path: {}", path);
+            }
+            counter_handles.push(add_perf_counter(&query_handle, &path)?);
+            let instance = counter_path_instance(&path).unwrap_or(&path);
+            instances.push(instance.to_owned());
+        }
 
         Ok(Self {
             query_handle,
             counter_handles,
+            instances,
+            instance_values: Vec::new(),
+            scale,
+            format,
         })
     }
 
-    pub fn start(&self) -> Result<()> {
+    /// The counter instances this tracker samples.
+    pub fn instances(&self) -> &[String] {
+        &self.instances
+    }
+
+    fn collect_query_data(&self) -> Result<()> {
+        unsafe { PDH_FUNCTION(PdhCollectQueryData(self.query_handle.0)).ok() }
+    }
+}
+
+impl UtilizationSource for PerfTracker {
+    type Error = windows::core::Error;
+
+    fn start(&mut self) -> Result<()> {
         self.collect_query_data()
     }
 
-    pub fn get_current_value(&self) -> Result<f64> {
+    /// Reads every counter instance. The value is their sum.
+    fn sample(&mut self) -> Result<f64> {
         self.collect_query_data()?;
 
-        let mut utilization_value = 0.0;
-        for counter_handle in &self.counter_handles {
+        self.instance_values.clear();
+        for (counter_handle, instance) in self.counter_handles.iter().zip(&self.instances) {
             let counter_value = unsafe {
                 let mut counter_type = 0;
                 let mut counter_value = PDH_FMT_COUNTERVALUE::default();
                 PDH_FUNCTION(PdhGetFormattedCounterValue(
                     *counter_handle,
-                    PDH_FMT_DOUBLE,
+                    self.format,
                     Some(&mut counter_type),
                     &mut counter_value,
                 ))
                 .ok()?;
                 counter_value
             };
-            assert_eq!(counter_value.CStatus, PDH_CSTATUS_VALID_DATA);
-            let value = unsafe { counter_value.Anonymous.doubleValue };
-            utilization_value += value;
+            if counter_value.CStatus != PDH_CSTATUS_VALID_DATA
+                && counter_value.CStatus != PDH_CSTATUS_NEW_DATA
+            {
+                return Err(windows::core::Error::new(
+                    E_FAIL,
+                    format!(
+                        "The counter for {} has no valid data (status {:#010X})",
+                        instance, counter_value.CStatus
+                    ),
+                ));
+            }
+            let value = unsafe { counter_value.Anonymous.doubleValue } * self.scale;
+            self.instance_values.push(value);
         }
-        Ok(utilization_value)
+        Ok(self.instance_values.iter().sum())
     }
 
-    pub fn close(&mut self) -> Result<()> {
+    fn close(&mut self) -> Result<()> {
         self.query_handle.close_query()
     }
 
-    fn collect_query_data(&self) -> Result<()> {
-        unsafe { PDH_FUNCTION(PdhCollectQueryData(self.query_handle.0)).ok() }
+    fn instance_names(&self) -> Vec<String> {
+        self.instances.clone()
+    }
+
+    fn instance_values(&self) -> Vec<f64> {
+        self.instance_values.clone()
     }
 }
 
@@ -9,88 +9,199 @@ use std::{
 use windows::{
     Foundation::TypedEventHandler,
     System::{DispatcherQueue, DispatcherQueueHandler, DispatcherQueueTimer},
-    Win32::Foundation::LUID,
+    Win32::Foundation::E_FAIL,
     core::Result,
 };
 
-use crate::{adapter::Adapter, perf::PerfTracker};
+use crate::{
+    adapter::Adapter,
+    engine::{Measurement, Series},
+    error::WgcPerfError,
+    perf::PerfTracker,
+    process::Process,
+    sampling::{PerfTrackerBundle, Sampler, SamplingState, SourceSamples, UtilizationSource},
+};
 
-pub struct PerfSession {
-    _inner: Arc<RwLock<PerfSessionInner>>,
-    receiver: Receiver<Vec<Vec<f64>>>,
+pub struct PerfSession<S: UtilizationSource<Error = windows::core::Error>> {
+    _inner: Arc<RwLock<PerfSessionInner<S>>>,
+    receiver: Receiver<Result<Vec<SourceSamples>>>,
 }
 
-struct PerfSessionInner {
-    target_length: Duration,
-    current_length: Duration,
-    tick_length: Duration,
-    trackers: PerfTrackerBundle,
+struct PerfSessionInner<S: UtilizationSource<Error = windows::core::Error>> {
+    state: SamplingState<S>,
     timer: DispatcherQueueTimer,
     timer_token: Option<i64>,
-    sender: Sender<Vec<Vec<f64>>>,
+    sender: Sender<Result<Vec<SourceSamples>>>,
 }
 // SAFETY: This will only ever be accessed by the UI thread, but the DispatcherQueueTimer's
 //         Tick event requires Send and Sync.
-unsafe impl Send for PerfSessionInner {}
-unsafe impl Sync for PerfSessionInner {}
-
-struct PerfTrackerBundle {
-    trackers: Vec<Option<PerfTracker>>,
-    samples: Vec<Vec<f64>>,
-}
+unsafe impl<S: UtilizationSource<Error = windows::core::Error>> Send for PerfSessionInner<S> {}
+unsafe impl<S: UtilizationSource<Error = windows::core::Error>> Sync for PerfSessionInner<S> {}
 
-impl PerfSession {
+impl PerfSession<PerfTracker> {
     pub fn run_on_thread(
         thread: &DispatcherQueue,
         duration: Duration,
-        process_id: u32,
+        sample_interval: Duration,
+        processes: &[Process],
         adapters: &[Adapter],
+        series: &[Series],
+        verbose: bool,
+    ) -> Result<Vec<SourceSamples>> {
+        let series: Vec<_> = series
+            .iter()
+            .map(|x| {
+                let process_id = processes[x.process_index].pid;
+                let luid = x.adapter_index.map(|x| adapters[x].luid);
+                (process_id, luid, x.measurement.clone())
+            })
+            .collect();
+        Self::run_sources_on_thread(thread, duration, sample_interval, move || {
+            series
+                .iter()
+                .map(|(process_id, luid, measurement)| {
+                    let tracker = match (measurement, luid) {
+                        // Adapters don't have every engine type (e.g. the Basic
+                        // Render Driver, or most types in `--engines all`), so
+                        // those series are recorded without samples
+                        (Measurement::Utilization { engine }, _) => {
+                            return Ok(PerfTracker::new(*process_id, *luid, engine, verbose).ok());
+                        }
+                        (Measurement::DedicatedMemory, Some(luid)) => {
+                            PerfTracker::gpu_memory(*process_id, *luid, "Dedicated Usage", verbose)
+                        }
+                        (Measurement::SharedMemory, Some(luid)) => {
+                            PerfTracker::gpu_memory(*process_id, *luid, "Shared Usage", verbose)
+                        }
+                        (Measurement::Cpu, _) => PerfTracker::cpu(*process_id, verbose),
+                        // Memory is only tracked per adapter
+                        (Measurement::DedicatedMemory | Measurement::SharedMemory, None) => {
+                            return Ok(None);
+                        }
+                    };
+                    // A CPU or memory counter set that is missing or disabled
+                    // would otherwise be recorded as zeros
+                    tracker.map(Some).map_err(|error| {
+                        windows::core::Error::new(
+                            error.code(),
+                            format!(
+                                "Could not add the {} counters of process {}: {}",
+                                measurement.label(),
+                                process_id,
+                                error
+                            ),
+                        )
+                    })
+                })
+                .collect()
+        })
+    }
+}
+
+/// Samples the GPU utilization, GPU memory and CPU usage of each process for
+/// each series using `PerfSession`s run on the given thread.
+pub struct PerfSessionSampler<'a> {
+    thread: &'a DispatcherQueue,
+    sample_interval: Duration,
+    processes: &'a [Process],
+    adapters: &'a [Adapter],
+    series: &'a [Series],
+    verbose: bool,
+}
+
+impl<'a> PerfSessionSampler<'a> {
+    pub fn new(
+        thread: &'a DispatcherQueue,
+        sample_interval: Duration,
+        processes: &'a [Process],
+        adapters: &'a [Adapter],
+        series: &'a [Series],
         verbose: bool,
-    ) -> Result<Vec<Vec<f64>>> {
+    ) -> Self {
+        Self {
+            thread,
+            sample_interval,
+            processes,
+            adapters,
+            series,
+            verbose,
+        }
+    }
+}
+
+impl Sampler for PerfSessionSampler<'_> {
+    type Error = WgcPerfError;
+
+    fn sample_for(
+        &mut self,
+        duration: Duration,
+    ) -> std::result::Result<Vec<SourceSamples>, WgcPerfError> {
+        PerfSession::run_on_thread(
+            self.thread,
+            duration,
+            self.sample_interval,
+            self.processes,
+            self.adapters,
+            self.series,
+            self.verbose,
+        )
+        .map_err(|error| WgcPerfError::counter("Could not sample the performance counters", error))
+    }
+}
+
+impl<S: UtilizationSource<Error = windows::core::Error> + 'static> PerfSession<S> {
+    /// Samples the sources returned by `create_sources` every
+    /// `sample_interval` for the given duration. The sources are created,
+    /// sampled and closed on `thread`.
+    pub fn run_sources_on_thread<F>(
+        thread: &DispatcherQueue,
+        duration: Duration,
+        sample_interval: Duration,
+        create_sources: F,
+    ) -> Result<Vec<SourceSamples>>
+    where
+        F: FnOnce() -> Result<Vec<Option<S>>> + Send + 'static,
+    {
         let (inner, receiver) = {
             let (sender, receiver) = channel();
-            let adapter_luids: Vec<_> = adapters.iter().map(|x| x.luid).collect();
+            let mut create_sources = Some(create_sources);
             thread.TryEnqueue(&DispatcherQueueHandler::new(move || -> Result<()> {
-                let result = PerfSessionInner::start(duration, process_id, &adapter_luids, verbose);
-                sender.send(result).unwrap();
+                let create_sources = create_sources.take().unwrap();
+                let result = create_sources().and_then(|sources| {
+                    let trackers = PerfTrackerBundle::new(sources);
+                    PerfSessionInner::start(duration, sample_interval, trackers)
+                });
+                // The receiver only goes away if we stopped waiting
+                let _ = sender.send(result);
                 Ok(())
             }))?;
-            receiver.recv().unwrap()?
+            receiver.recv().map_err(|_| thread_stopped())??
         };
 
         let this = Self {
             _inner: inner,
             receiver,
         };
-        let samples = this.receiver.recv().unwrap();
-
-        Ok(samples)
+        this.receiver.recv().map_err(|_| thread_stopped())?
     }
 }
 
-impl PerfSessionInner {
+impl<S: UtilizationSource<Error = windows::core::Error> + 'static> PerfSessionInner<S> {
     fn start(
         duration: Duration,
-        process_id: u32,
-        adapter_luids: &[LUID],
-        verbose: bool,
-    ) -> Result<(Arc<RwLock<Self>>, Receiver<Vec<Vec<f64>>>)> {
-        let target_length = duration;
-        let current_length = Duration::from_secs(0);
-        let trackers = PerfTrackerBundle::new(adapter_luids, process_id, verbose)?;
+        sample_interval: Duration,
+        trackers: PerfTrackerBundle<S>,
+    ) -> Result<(Arc<RwLock<Self>>, Receiver<Result<Vec<SourceSamples>>>)> {
+        let state = SamplingState::new(duration, sample_interval, trackers);
 
         let dispatcher_queue = DispatcherQueue::GetForCurrentThread()?;
         let timer = dispatcher_queue.CreateTimer()?;
-        let tick_length = Duration::from_millis(500);
-        timer.SetInterval(tick_length.into())?;
+        timer.SetInterval(state.next_tick_length().into())?;
         timer.SetIsRepeating(true)?;
 
         let (sender, receiver) = channel();
         let inner = Arc::new(RwLock::new(Self {
-            target_length,
-            current_length,
-            tick_length,
-            trackers,
+            state,
             timer: timer.clone(),
             timer_token: None,
             sender,
@@ -101,7 +212,7 @@ impl PerfSessionInner {
             move |timer, _| -> Result<()> {
                 if let Some(inner) = inner.upgrade() {
                     let mut inner = inner.write().unwrap();
-                    inner.on_tick()?;
+                    inner.on_tick();
                 } else {
                     let timer = timer.unwrap();
                     timer.Stop()?;
@@ -114,27 +225,39 @@ impl PerfSessionInner {
         {
             let mut inner = inner.write().unwrap();
             inner.timer_token = Some(token);
-            inner.trackers.start()?;
+            inner.state.start()?;
             inner.timer.Start()?;
         }
         Ok((inner, receiver))
     }
 
-    fn on_tick(&mut self) -> Result<()> {
-        self.trackers.update_samples()?;
+    /// Samples the sources, and sends the samples or the first error to
+    /// the waiting thread once the session is over. Errors returned from the
+    /// Tick handler go nowhere, so they must be sent too.
+    fn on_tick(&mut self) {
+        let Some(result) = self.tick().transpose() else {
+            return;
+        };
+        let stopped = self.timer.Stop();
+        let _ = self
+            .sender
+            .send(result.and_then(|samples| stopped.map(|_| samples)));
+    }
 
-        self.current_length += self.tick_length;
-        if self.current_length >= self.target_length {
-            self.timer.Stop()?;
-            let samples: Vec<_> = self.trackers.samples.drain(..).collect();
-            self.sender.send(samples).unwrap();
+    fn tick(&mut self) -> Result<Option<Vec<SourceSamples>>> {
+        let samples = self.state.on_tick()?;
+        if samples.is_none() {
+            // The next tick may need to be shorter to end on time.
+            let next_tick_length = self.state.next_tick_length();
+            if next_tick_length != self.state.tick_length() {
+                self.timer.SetInterval(next_tick_length.into())?;
+            }
         }
-
-        Ok(())
+        Ok(samples)
     }
 }
 
-impl Drop for PerfSessionInner {
+impl<S: UtilizationSource<Error = windows::core::Error>> Drop for PerfSessionInner<S> {
     fn drop(&mut self) {
         if let Some(token) = self.timer_token.take() {
             let _ = self.timer.RemoveTick(token);
@@ -142,31 +265,11 @@ impl Drop for PerfSessionInner {
     }
 }
 
-impl PerfTrackerBundle {
-    fn new(adapter_luids: &[LUID], pid: u32, verbose: bool) -> Result<Self> {
-        let trackers: Vec<_> = adapter_luids
-            .iter()
-            .map(|x| PerfTracker::new(pid, Some(*x), verbose).ok())
-            .collect();
-        let samples = vec![Vec::new(); trackers.len()];
-        Ok(Self { trackers, samples })
-    }
-
-    fn start(&self) -> Result<()> {
-        for tracker in &self.trackers {
-            if let Some(tracker) = tracker {
-                tracker.start()?;
-            }
-        }
-        Ok(())
-    }
-
-    fn update_samples(&mut self) -> Result<()> {
-        for (tracker, samples) in self.trackers.iter().zip(self.samples.iter_mut()) {
-            if let Some(tracker) = tracker.as_ref() {
-                samples.push(tracker.get_current_value()?);
-            }
-        }
-        Ok(())
-    }
+/// The sampling thread dropped its sender without sending the samples, e.g.
+/// because it was shut down.
+fn thread_stopped() -> windows::core::Error {
+    windows::core::Error::new(
+        E_FAIL,
+        "The sampling thread stopped before sampling finished",
+    )
 }
@@ -1,20 +1,106 @@
+use std::path::Path;
+
 use processdumper::{find_process_id_with_name_in_session, get_session_for_current_process};
-use windows::Win32::Foundation::E_FAIL;
+use windows::{
+    Win32::{
+        Foundation::{CloseHandle, E_FAIL, E_INVALIDARG},
+        System::Threading::{
+            OpenProcess, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+            QueryFullProcessImageNameW,
+        },
+    },
+    core::PWSTR,
+};
+
+use crate::{
+    compositor::{CompositorLocator, CompositorQuery},
+    process::Process,
+};
+
+/// Finds processes with processdumper.
+pub struct WindowsCompositorLocator;
+
+impl CompositorLocator for WindowsCompositorLocator {
+    type Error = windows::core::Error;
+
+    fn current_session(&self) -> windows::core::Result<u32> {
+        get_session_for_current_process()
+    }
+
+    fn find_in_session(&self, name: &str, session: u32) -> windows::core::Result<Option<u32>> {
+        find_process_id_with_name_in_session(name, session)
+    }
+
+    fn process_name(&self, pid: u32) -> windows::core::Result<Option<String>> {
+        match get_process_name(pid) {
+            Ok(name) => Ok(Some(name)),
+            // OpenProcess fails with E_INVALIDARG for pids that don't exist
+            Err(error) if error.code() == E_INVALIDARG => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
 
 pub fn get_current_dwm_pid() -> windows::core::Result<u32> {
-    // During RDP sessions, you'll have multiple sessions and muiltple
-    // DWMs. We want the one the user is currently using, so find the
-    // session our program is running in.
+    find_compositor(&CompositorQuery::dwm()).map(|process| process.pid)
+}
+
+/// Finds the compositor, failing if there's no process matching the query.
+pub fn find_compositor(query: &CompositorQuery) -> windows::core::Result<Process> {
+    WindowsCompositorLocator.locate(query)?.ok_or_else(|| {
+        let message = match query {
+            CompositorQuery::Pid(pid) => format!("Could not find process {}!", pid),
+            CompositorQuery::Names(names) => {
+                format!(
+                    "Could not find a {} process for this session!",
+                    names.join(" or ")
+                )
+            }
+            CompositorQuery::Session { session, names } => format!(
+                "Could not find a {} process in session {}!",
+                names.join(" or "),
+                session
+            ),
+        };
+        windows::core::Error::new(E_FAIL, message)
+    })
+}
+
+/// Finds a process by executable name (e.g. `dwm.exe`) in the session our
+/// program is running in.
+pub fn find_process_in_current_session(name: &str) -> windows::core::Result<u32> {
     let current_session = get_session_for_current_process()?;
-    let process_id = if let Some(process_id) =
-        find_process_id_with_name_in_session("dwm.exe", current_session)?
-    {
-        process_id
-    } else {
-        return Err(windows::core::Error::new(
-            E_FAIL,
-            "Could not find a dwm process for this session!",
-        ));
-    };
+    let process_id =
+        if let Some(process_id) = find_process_id_with_name_in_session(name, current_session)? {
+            process_id
+        } else {
+            return Err(windows::core::Error::new(
+                E_FAIL,
+                format!("Could not find a {} process for this session!", name),
+            ));
+        };
     Ok(process_id)
 }
+
+/// Looks up the executable name of a process, e.g. `dwm.exe`.
+pub fn get_process_name(process_id: u32) -> windows::core::Result<String> {
+    let path = unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id)?;
+        let mut buffer = vec![0u16; 1024];
+        let mut size = buffer.len() as u32;
+        let result = QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &mut size,
+        );
+        let _ = CloseHandle(process);
+        result?;
+        String::from_utf16_lossy(&buffer[..size as usize])
+    };
+    let name = Path::new(&path)
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or(path);
+    Ok(name)
+}
@@ -0,0 +1,189 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PassKind {
+    Baseline,
+    Wgc,
+    Dda,
+}
+
+impl PassKind {
+    pub const ALL: [PassKind; 3] = [PassKind::Baseline, PassKind::Wgc, PassKind::Dda];
+
+    /// The name used in results files.
+    pub fn name(self) -> &'static str {
+        match self {
+            PassKind::Baseline => "baseline",
+            PassKind::Wgc => "wgc",
+            PassKind::Dda => "dda",
+        }
+    }
+
+    /// The name used in console output.
+    pub fn display_name(self) -> &'static str {
+        match self {
+            PassKind::Baseline => "baseline",
+            PassKind::Wgc => "WGC",
+            PassKind::Dda => "DDA",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedPass {
+    /// The zero-based iteration this pass belongs to.
+    pub iteration: usize,
+    /// The index of the pass in the scenario.
+    pub index: usize,
+}
+
+/// How passes are ordered within each iteration.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PassOrder {
+    /// The scenario's order in every iteration.
+    Fixed,
+    /// A random order in every iteration.
+    Shuffle,
+    /// Alternates between the fixed order and its reverse.
+    Abba,
+}
+
+/// Lays out `pass_count` passes for each iteration according to `order`.
+/// The seed is only used when shuffling, and the same seed always produces
+/// the same plan.
+pub fn build_plan(
+    pass_count: usize,
+    order: PassOrder,
+    iterations: usize,
+    seed: u64,
+) -> Vec<PlannedPass> {
+    let mut rng = SplitMix64::new(seed);
+    let mut plan = Vec::with_capacity(pass_count * iterations);
+    for iteration in 0..iterations {
+        let mut indices: Vec<_> = (0..pass_count).collect();
+        match order {
+            PassOrder::Fixed => {}
+            PassOrder::Shuffle => rng.shuffle(&mut indices),
+            PassOrder::Abba => {
+                if iteration % 2 == 1 {
+                    indices.reverse();
+                }
+            }
+        }
+        plan.extend(
+            indices
+                .into_iter()
+                .map(|index| PlannedPass { iteration, index }),
+        );
+    }
+    plan
+}
+
+/// The number of iterations covered by a plan.
+pub fn plan_iterations(plan: &[PlannedPass]) -> usize {
+    plan.iter().map(|x| x.iteration + 1).max().unwrap_or(0)
+}
+
+/// A small, fast PRNG. We only need reproducible shuffles, not
+/// cryptographic quality, and its output must not change between versions.
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a value in `0..bound`. `bound` must be non-zero.
+    pub fn next_below(&mut self, bound: u64) -> u64 {
+        // Rejection sampling to avoid modulo bias
+        let zone = u64::MAX - (u64::MAX % bound);
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+
+    /// Fisher-Yates shuffle
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.next_below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(plan: &[PlannedPass], iteration: usize) -> Vec<usize> {
+        plan.iter()
+            .filter(|x| x.iteration == iteration)
+            .map(|x| x.index)
+            .collect()
+    }
+
+    #[test]
+    fn fixed_repeats_the_scenario_order() {
+        let plan = build_plan(3, PassOrder::Fixed, 2, 7);
+        assert_eq!(plan.len(), 6);
+        assert_eq!(indices(&plan, 0), [0, 1, 2]);
+        assert_eq!(indices(&plan, 1), [0, 1, 2]);
+        assert_eq!(plan_iterations(&plan), 2);
+    }
+
+    #[test]
+    fn abba_reverses_odd_iterations() {
+        let plan = build_plan(3, PassOrder::Abba, 4, 0);
+        assert_eq!(indices(&plan, 0), [0, 1, 2]);
+        assert_eq!(indices(&plan, 1), [2, 1, 0]);
+        assert_eq!(indices(&plan, 2), [0, 1, 2]);
+        assert_eq!(indices(&plan, 3), [2, 1, 0]);
+    }
+
+    #[test]
+    fn shuffle_is_a_permutation_per_iteration() {
+        let plan = build_plan(5, PassOrder::Shuffle, 20, 1234);
+        assert_eq!(plan_iterations(&plan), 20);
+        let mut orders = Vec::new();
+        for iteration in 0..20 {
+            let order = indices(&plan, iteration);
+            let mut sorted = order.clone();
+            sorted.sort();
+            assert_eq!(sorted, [0, 1, 2, 3, 4]);
+            orders.push(order);
+        }
+        // 20 iterations of 5 passes are practically never all the same
+        assert!(orders.iter().any(|x| *x != orders[0]));
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_plan() {
+        let plan = build_plan(4, PassOrder::Shuffle, 10, 42);
+        assert_eq!(plan, build_plan(4, PassOrder::Shuffle, 10, 42));
+        assert_ne!(plan, build_plan(4, PassOrder::Shuffle, 10, 43));
+    }
+
+    #[test]
+    fn split_mix_output_is_stable() {
+        // The reference implementation's first output for seed 0
+        assert_eq!(SplitMix64::new(0).next_u64(), 0xE220A8397B1DCDAF);
+    }
+}
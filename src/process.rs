@@ -0,0 +1,71 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// A process whose usage is recorded.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Process {
+    /// The executable name, e.g. `dwm.exe`.
+    pub name: String,
+    pub pid: u32,
+}
+
+impl Display for Process {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.pid)
+    }
+}
+
+/// Pairs each process in `new` with one in `old`. Pids change between runs,
+/// so processes are matched by name (case-insensitively), in order.
+pub fn match_processes(old: &[Process], new: &[Process]) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let mut old_used = vec![false; old.len()];
+    for (new_index, new_process) in new.iter().enumerate() {
+        if let Some(old_index) = old.iter().enumerate().position(|(i, old_process)| {
+            !old_used[i] && old_process.name.eq_ignore_ascii_case(&new_process.name)
+        }) {
+            old_used[old_index] = true;
+            matches.push((old_index, new_index));
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::process;
+
+    use super::*;
+
+    #[test]
+    fn processes_are_matched_by_name_ignoring_case() {
+        let old = [process("dwm.exe", 1), process("explorer.exe", 2)];
+        let new = [process("Explorer.EXE", 20), process("DWM.exe", 10)];
+        assert_eq!(match_processes(&old, &new), [(1, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn duplicate_names_are_matched_in_order() {
+        let old = [
+            process("app.exe", 1),
+            process("dwm.exe", 2),
+            process("app.exe", 3),
+        ];
+        let new = [
+            process("app.exe", 30),
+            process("app.exe", 10),
+            process("app.exe", 20),
+        ];
+        assert_eq!(match_processes(&old, &new), [(0, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn unmatched_processes_are_left_out() {
+        let old = [process("dwm.exe", 1), process("gnome-shell", 2)];
+        let new = [process("kwin_wayland", 3), process("dwm.exe", 4)];
+        assert_eq!(match_processes(&old, &new), [(0, 1)]);
+        assert_eq!(match_processes(&[], &new), []);
+        assert_eq!(match_processes(&old, &[]), []);
+    }
+}
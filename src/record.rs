@@ -0,0 +1,537 @@
+use std::{sync::mpsc::channel, time::Duration};
+
+use wgcperf::{
+    adapter::Adapter,
+    aggregate::{print_summary, summarize_passes},
+    compositor::CompositorQuery,
+    csv,
+    engine::build_series,
+    error::WgcPerfError,
+    monitor::Monitor,
+    monitor_selector::{MonitorSelector, select_monitor},
+    perf_session::PerfSessionSampler,
+    pid::{find_compositor, find_process_in_current_session, get_process_name},
+    plan::{PassKind, PassOrder, build_plan},
+    process::Process,
+    results::{MonitorDetails, RunConfig, RunResults},
+    runner::{BoxedCaptureSink, print_plan, run_plan},
+    scenario::{Scenario, SinkOptions, WorkloadSettings},
+    sinks::{CaptureSink, dda::DdaCaptureSink, wgc::WgcCaptureSink},
+    window::Window,
+    windows_utils::{
+        composition::CompositionInterop,
+        d3d::create_d3d_device_on_adapter,
+        dxgi::{DxgiAdapterIter, DxgiOutputIter},
+    },
+};
+use windows::{
+    System::{DispatcherQueue, DispatcherQueueController, DispatcherQueueHandler},
+    UI::{
+        Color,
+        Composition::{
+            AnimationIterationBehavior, Core::CompositorController, Desktop::DesktopWindowTarget,
+        },
+    },
+    Win32::{
+        Foundation::E_FAIL,
+        Graphics::{
+            Direct3D11::ID3D11Device,
+            Dxgi::{CreateDXGIFactory1, IDXGIAdapter1, IDXGIFactory1, IDXGIOutput1},
+            Gdi::{GetMonitorInfoW, HMONITOR, MONITORINFO},
+        },
+        System::{
+            WinRT::{RO_INIT_MULTITHREADED, RoInitialize},
+            WindowsProgramming::MulDiv,
+        },
+        UI::HiDpi::{
+            DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, GetDpiForMonitor, MDT_EFFECTIVE_DPI,
+            SetProcessDpiAwarenessContext,
+        },
+    },
+    core::{Interface, h},
+};
+use windows_numerics::{Vector2, Vector3};
+
+use crate::{
+    cli::{AdhocArgs, RunArgs},
+    save_error,
+};
+
+/// Records the passes of the scenario.
+pub fn run(args: RunArgs, scenario: Scenario) -> Result<RunResults, WgcPerfError> {
+    // Cli
+    let seed = args.seed.or(scenario.seed).unwrap_or_else(random_seed);
+    let sample_interval = Duration::from_millis(scenario.sample_interval_ms);
+    let alpha = args.alpha;
+    let verbose = args.verbose;
+    let output_path = args.output;
+    let csv_path = args.csv;
+    let engines = args.engines.0;
+    let memory = args.memory;
+    let cpu = args.cpu;
+    let record_self = args.record_self;
+    let pids = args.pids;
+    let process_names = args.process_names;
+
+    init_process()?;
+    let env = TestEnvironment::new(&args.monitor, &scenario.workload)?;
+
+    // Get the DWM's pid and any others we were asked to record
+    let processes = find_processes(record_self, &pids, &process_names).map_err(|error| {
+        WgcPerfError::Config {
+            context: "Could not find the processes to record".to_owned(),
+            source: Some(error.into()),
+        }
+    })?;
+    if processes.len() > 1 {
+        let names: Vec<_> = processes.iter().map(|x| x.to_string()).collect();
+        println!("Processes: {}", names.join(", "));
+    }
+
+    // Run the test
+    let series = build_series(processes.len(), env.adapters.len(), &engines, memory, cpu);
+    let mut sampler = PerfSessionSampler::new(
+        &env.ui_queue,
+        sample_interval,
+        &processes,
+        &env.adapters,
+        &series,
+        verbose,
+    );
+    if let Some(name) = &scenario.name {
+        println!("Scenario: {}", name);
+    }
+    let order = scenario.order;
+    let plan = build_plan(scenario.passes.len(), order, scenario.iterations, seed);
+    if order == PassOrder::Shuffle {
+        println!("Shuffle seed: {}", seed);
+    }
+    if order != PassOrder::Fixed {
+        print_plan(&scenario, &plan);
+    }
+    let passes = run_plan(
+        &scenario,
+        &plan,
+        &mut sampler,
+        |pass| -> Result<Option<BoxedCaptureSink<WgcPerfError>>, WgcPerfError> {
+            let sink: BoxedCaptureSink<WgcPerfError> = match pass.kind {
+                PassKind::Baseline => return Ok(None),
+                PassKind::Wgc => Box::new(
+                    WgcCaptureSink::new(&env.d3d_device, env.monitor_handle, &pass.sink).map_err(
+                        |error| WgcPerfError::capture("Could not create the WGC capture", error),
+                    )?,
+                ),
+                PassKind::Dda => Box::new(
+                    DdaCaptureSink::new(&env.d3d_device, env.output.clone()).map_err(|error| {
+                        WgcPerfError::capture("Could not create the DDA capture", error)
+                    })?,
+                ),
+            };
+            Ok(Some(sink))
+        },
+        &env.adapters,
+        &processes,
+        &series,
+        alpha,
+    )?;
+
+    let summary = summarize_passes(&passes, alpha);
+    print_summary(&env.adapters, &processes, &summary);
+
+    let adapters = env.adapters.clone();
+    let monitor_details = env.monitor_details.clone();
+    env.close()?;
+
+    // Save samples
+    if let Some(csv_path) = csv_path {
+        csv::save_samples_csv(&csv_path, &adapters, &processes, &passes)
+            .map_err(|error| save_error(&csv_path, error))?;
+        println!("Saved samples to \"{}\"", csv_path.display());
+    }
+    let run_config = RunConfig {
+        monitor: Some(monitor_details.index),
+        seed,
+        alpha,
+        engines,
+        memory,
+        cpu,
+        scenario,
+    };
+    let results = RunResults::new(
+        run_config,
+        Some(monitor_details),
+        adapters,
+        processes,
+        passes,
+        summary,
+    );
+    if let Some(output_path) = output_path {
+        results
+            .save(&output_path)
+            .map_err(|error| save_error(&output_path, error))?;
+        println!("Saved results to \"{}\"", output_path.display());
+    }
+
+    Ok(results)
+}
+
+/// Runs WGC until the user says stop.
+pub fn adhoc(args: AdhocArgs) -> Result<(), WgcPerfError> {
+    init_process()?;
+    let env = TestEnvironment::new(&args.monitor, &WorkloadSettings::default())?;
+
+    let sink_options = SinkOptions {
+        use_dirty_rects: args.use_dirty_rects,
+        ..Default::default()
+    };
+    let mut wgc_sink = WgcCaptureSink::new(&env.d3d_device, env.monitor_handle, &sink_options)
+        .map_err(|error| WgcPerfError::capture("Could not create the WGC capture", error))?;
+    wgc_sink.start()?;
+    println!("Press ENTER to stop...");
+    std::io::Read::read(&mut std::io::stdin(), &mut [0])
+        .map_err(|error| WgcPerfError::io("Could not read from stdin", error))?;
+    wgc_sink.stop()?;
+
+    env.close()
+}
+
+pub fn list_monitors() -> Result<(), WgcPerfError> {
+    init_process()?;
+    let monitors = Monitor::enumerate_all()
+        .map_err(|error| WgcPerfError::device("Could not enumerate the monitors", error))?;
+    println!("Monitors:");
+    for (i, monitor) in monitors.iter().enumerate() {
+        let bounds = monitor.bounds();
+        println!(
+            "  {} - {} ({}) - {} Hz - {}x{} at ({}, {}){}",
+            i,
+            monitor.display_name(),
+            monitor.device_name(),
+            monitor.display_frequency(),
+            bounds.right - bounds.left,
+            bounds.bottom - bounds.top,
+            bounds.left,
+            bounds.top,
+            if monitor.is_primary() {
+                " - primary"
+            } else {
+                ""
+            }
+        );
+    }
+    Ok(())
+}
+
+pub fn list_adapters() -> Result<(), WgcPerfError> {
+    let adapters = Adapter::enumerate_all()
+        .map_err(|error| WgcPerfError::device("Could not enumerate the adapters", error))?;
+    println!("Adapters:");
+    for (i, adapter) in adapters.iter().enumerate() {
+        println!("  {} - {} - {}", i, adapter.name, adapter.luid);
+    }
+    Ok(())
+}
+
+fn init_process() -> Result<(), WgcPerfError> {
+    unsafe {
+        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)
+            .map_err(|error| WgcPerfError::device("Could not set the DPI awareness", error))?;
+    }
+    unsafe { RoInitialize(RO_INIT_MULTITHREADED) }
+        .map_err(|error| WgcPerfError::device("Could not initialize WinRT", error))
+}
+
+/// The dummy window and its animated content, and the D3D device for the
+/// monitor it sits on.
+struct TestEnvironment {
+    ui_thread: DispatcherQueueController,
+    ui_queue: DispatcherQueue,
+    window: Box<Window>,
+    _compositor_controller: CompositorController,
+    _target: DesktopWindowTarget,
+    monitor_handle: HMONITOR,
+    monitor_details: MonitorDetails,
+    d3d_device: ID3D11Device,
+    output: IDXGIOutput1,
+    adapters: Vec<Adapter>,
+}
+
+impl TestEnvironment {
+    fn new(
+        monitor_selector: &MonitorSelector,
+        workload: &WorkloadSettings,
+    ) -> Result<Self, WgcPerfError> {
+        let monitors = Monitor::enumerate_all()
+            .map_err(|error| WgcPerfError::device("Could not enumerate the monitors", error))?;
+        let descriptors: Vec<_> = monitors
+            .iter()
+            .enumerate()
+            .map(|(i, monitor)| monitor.descriptor(i))
+            .collect();
+        let monitor_index = select_monitor(monitor_selector, &descriptors)?;
+        let monitor = &monitors[monitor_index];
+        let monitor_handle = monitor.handle();
+        let monitor_error = |error: windows::core::Error| {
+            WgcPerfError::device(
+                format!("Could not query monitor {}", monitor.display_name()),
+                error,
+            )
+        };
+        let monitor_info = unsafe {
+            let mut info = MONITORINFO::default();
+            info.cbSize = std::mem::size_of_val(&info) as u32;
+            GetMonitorInfoW(monitor_handle, &mut info)
+                .ok()
+                .map_err(monitor_error)?;
+            info
+        };
+        let work_area = monitor_info.rcWork;
+        println!("Monitor details:");
+        println!("  index: {}", monitor_index);
+        println!("  handle: {:010X}", monitor_handle.0 as usize);
+        println!("  name: {}", monitor.display_name());
+        println!("  frequency: {} Hz", monitor.display_frequency());
+        println!();
+        let monitor_details = MonitorDetails {
+            index: monitor_index,
+            name: monitor.display_name().to_owned(),
+            device_name: monitor.device_name().to_owned(),
+            frequency: monitor.display_frequency(),
+        };
+
+        // Compute window position
+        let (dpix, dpiy) = unsafe {
+            let mut dpix = 0;
+            let mut dpiy = 0;
+            GetDpiForMonitor(monitor_handle, MDT_EFFECTIVE_DPI, &mut dpix, &mut dpiy)
+                .map_err(monitor_error)?;
+            (dpix, dpiy)
+        };
+        let window_size = workload.window_size as i32;
+        let window_width = unsafe { MulDiv(window_size, dpix as i32, 96) };
+        let window_height = unsafe { MulDiv(window_size, dpiy as i32, 96) };
+        let work_area_width = work_area.right - work_area.left;
+        let work_area_height = work_area.bottom - work_area.top;
+        let window_x = ((work_area_width - window_width) / 2) + work_area.left;
+        let window_y = ((work_area_height - window_height) / 2) + work_area.top;
+
+        let test_window = TestWindow::new(
+            window_x,
+            window_y,
+            window_width as u32,
+            window_height as u32,
+            workload,
+        )
+        .map_err(|error| WgcPerfError::device("Could not create the test window", error))?;
+
+        // Initialize D3D
+        let dxgi_factory: IDXGIFactory1 = unsafe { CreateDXGIFactory1() }
+            .map_err(|error| WgcPerfError::device("Could not create a DXGI factory", error))?;
+        let dxgi_adapters: Vec<IDXGIAdapter1> = dxgi_factory.iter_adapters().collect();
+        let Some((adapter, output)) = dxgi_adapters.iter().find_map(|adapter| {
+            if let Some(output) = adapter.iter_outputs().find(|output| {
+                if let Ok(desc) = unsafe { output.GetDesc() } {
+                    desc.Monitor == monitor_handle
+                } else {
+                    false
+                }
+            }) {
+                Some((adapter.clone(), output))
+            } else {
+                None
+            }
+        }) else {
+            return Err(WgcPerfError::Device {
+                context: format!(
+                    "Could not find the adapter for monitor {}",
+                    monitor.display_name()
+                ),
+                source: None,
+            });
+        };
+        let d3d_device = create_d3d_device_on_adapter(&adapter)
+            .map_err(|error| WgcPerfError::device("Could not create the D3D device", error))?;
+        let output: IDXGIOutput1 = output
+            .cast()
+            .map_err(|error| WgcPerfError::device("Could not get the DXGI output", error))?;
+
+        // Collect all adapters
+        let adapters = {
+            let mut adapters = Vec::with_capacity(dxgi_adapters.len());
+            for dxgi_adapter in &dxgi_adapters {
+                adapters.push(Adapter::from_dxgi_adapter(&dxgi_adapter).map_err(|error| {
+                    WgcPerfError::device("Could not describe an adapter", error)
+                })?);
+            }
+            adapters
+        };
+        println!("Adapters:");
+        for (i, adapter) in adapters.iter().enumerate() {
+            println!("  {} - {}", i, adapter.name);
+        }
+        println!();
+
+        Ok(Self {
+            ui_thread: test_window.ui_thread,
+            ui_queue: test_window.ui_queue,
+            window: test_window.window,
+            _compositor_controller: test_window.compositor_controller,
+            _target: test_window.target,
+            monitor_handle,
+            monitor_details,
+            d3d_device,
+            output,
+            adapters,
+        })
+    }
+
+    /// Shuts down the UI thread and the window.
+    fn close(self) -> Result<(), WgcPerfError> {
+        self.window.close();
+        self.ui_thread
+            .ShutdownQueueAsync()
+            .and_then(|x| x.get())
+            .map_err(|error| WgcPerfError::device("Could not shut down the UI thread", error))
+    }
+}
+
+/// The dummy window on its own UI thread, and its animated content.
+struct TestWindow {
+    ui_thread: DispatcherQueueController,
+    ui_queue: DispatcherQueue,
+    window: Box<Window>,
+    compositor_controller: CompositorController,
+    target: DesktopWindowTarget,
+}
+
+impl TestWindow {
+    fn new(
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        workload: &WorkloadSettings,
+    ) -> windows::core::Result<Self> {
+        // Create the UI thread
+        let ui_thread = DispatcherQueueController::CreateOnDedicatedThread()?;
+        let ui_queue = ui_thread.DispatcherQueue()?;
+
+        // Create our dummy window
+        let window = run_on_queue(&ui_queue, move || {
+            Window::new("Dummy Content", x, y, width, height)
+        })?;
+
+        // Create our dummy content
+        let compositor_controller = run_on_queue(&ui_queue, CompositorController::new)?;
+        let compositor = compositor_controller.Compositor()?;
+        let root = compositor.CreateSpriteVisual()?;
+        root.SetRelativeSizeAdjustment(Vector2::new(1.0, 1.0))?;
+        root.SetBrush(&compositor.CreateColorBrushWithColor(Color {
+            A: 255,
+            R: 0,
+            G: 0,
+            B: 0,
+        })?)?;
+        let content = compositor.CreateSpriteVisual()?;
+        content.SetRelativeSizeAdjustment(Vector2 { X: 0.33, Y: 0.33 })?;
+        content.SetAnchorPoint(Vector2 { X: 0.5, Y: 0.5 })?;
+        content.SetRelativeOffsetAdjustment(Vector3 {
+            X: 0.5,
+            Y: 0.5,
+            Z: 0.0,
+        })?;
+        content.SetBrush(&compositor.CreateColorBrushWithColor(Color {
+            A: 255,
+            R: 255,
+            G: 0,
+            B: 0,
+        })?)?;
+        root.Children()?.InsertAtTop(&content)?;
+        let target = compositor.create_desktop_window_target(window.handle(), false)?;
+        target.SetRoot(&root)?;
+
+        // Animate the content
+        if workload.animate {
+            let easing = compositor.CreateLinearEasingFunction()?;
+            let animation = compositor.CreateScalarKeyFrameAnimation()?;
+            animation.InsertKeyFrameWithEasingFunction(0.0, 0.0, &easing)?;
+            animation.InsertKeyFrameWithEasingFunction(1.0, 360.0, &easing)?;
+            animation.SetDuration(Duration::from_millis(workload.animation_period_ms).into())?;
+            animation.SetIterationBehavior(AnimationIterationBehavior::Forever)?;
+            content.StartAnimation(h!("RotationAngleInDegrees"), &animation)?;
+        }
+        compositor_controller.Commit()?;
+
+        // Show the window
+        window.show();
+
+        Ok(Self {
+            ui_thread,
+            ui_queue,
+            window,
+            compositor_controller,
+            target,
+        })
+    }
+}
+
+/// Runs `f` on the queue's thread and waits for its result.
+fn run_on_queue<T, F>(queue: &DispatcherQueue, f: F) -> windows::core::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> windows::core::Result<T> + Send + 'static,
+{
+    let (sender, receiver) = channel();
+    let mut f = Some(f);
+    queue.TryEnqueue(&DispatcherQueueHandler::new(
+        move || -> windows::core::Result<()> {
+            if let Some(f) = f.take() {
+                // The receiver only goes away if we stopped waiting
+                let _ = sender.send(f());
+            }
+            Ok(())
+        },
+    ))?;
+    receiver.recv().map_err(|_| {
+        windows::core::Error::new(E_FAIL, "The UI thread stopped before running our work")
+    })?
+}
+
+/// Lists the processes to record: DWM first, then our own process if
+/// `record_self` is set, then the given pids and process names. Each process
+/// is only recorded once.
+fn find_processes(
+    record_self: bool,
+    pids: &[u32],
+    names: &[String],
+) -> windows::core::Result<Vec<Process>> {
+    let dwm = find_compositor(&CompositorQuery::dwm())?;
+    let mut targets = vec![(dwm.pid, Some(dwm.name))];
+    if record_self {
+        targets.push((std::process::id(), None));
+    }
+    targets.extend(pids.iter().map(|pid| (*pid, None)));
+    for name in names {
+        targets.push((find_process_in_current_session(name)?, Some(name.clone())));
+    }
+
+    let mut processes: Vec<Process> = Vec::new();
+    for (pid, name) in targets {
+        if processes.iter().any(|x| x.pid == pid) {
+            continue;
+        }
+        let name = match name {
+            Some(name) => name,
+            None => get_process_name(pid)?,
+        };
+        processes.push(Process { name, pid });
+    }
+    Ok(processes)
+}
+
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|x| x.as_nanos() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,165 @@
+use std::{io::Write, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adapter::Adapter,
+    aggregate::PassSummary,
+    engine::Series,
+    plan::PassKind,
+    process::Process,
+    runner::PassSamples,
+    sampling::Sample,
+    scenario::{PassSpec, Scenario},
+    significance::Comparison,
+    stats::SampleStats,
+};
+
+/// Bump this when the shape of the results document changes after a
+/// release.
+pub const RESULTS_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunResults {
+    pub version: u32,
+    pub config: RunConfig,
+    /// The monitor that was captured, or `None` for results replayed from a
+    /// counter log.
+    pub monitor: Option<MonitorDetails>,
+    pub adapters: Vec<Adapter>,
+    /// The processes that were recorded. DWM comes first.
+    pub processes: Vec<Process>,
+    /// Every pass, in the order it was recorded.
+    pub passes: Vec<PassResult>,
+    pub summary: Vec<PassSummary>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunConfig {
+    pub monitor: Option<usize>,
+    /// The seed used to shuffle the passes.
+    pub seed: u64,
+    pub alpha: f64,
+    /// The GPU engine types utilization was recorded for.
+    pub engines: Vec<String>,
+    /// Whether GPU memory usage was recorded.
+    pub memory: bool,
+    /// Whether the DWM's CPU usage was recorded.
+    pub cpu: bool,
+    pub scenario: Scenario,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonitorDetails {
+    pub index: usize,
+    pub name: String,
+    pub device_name: String,
+    pub frequency: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PassResult {
+    /// The name of the pass in the scenario.
+    pub name: String,
+    pub kind: PassKind,
+    pub iteration: usize,
+    pub duration_ms: u64,
+    /// The number of frames the capture sink received, if the pass used one.
+    pub frames: Option<usize>,
+    /// One entry per series, in the same order for every pass of a run.
+    pub adapters: Vec<AdapterSamples>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdapterSamples {
+    #[serde(flatten)]
+    pub series: Series,
+    pub stats: SampleStats,
+    /// The difference from the baseline pass of the same iteration, if this
+    /// is a capture pass.
+    pub comparison: Option<Comparison>,
+    /// The counter instances whose values are stored with each sample.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instances: Vec<String>,
+    pub samples: Vec<Sample>,
+}
+
+impl PassResult {
+    pub fn new(
+        pass: &PassSpec,
+        iteration: usize,
+        frames: Option<usize>,
+        series: &[Series],
+        adapter_samples: PassSamples,
+    ) -> Self {
+        let adapters = series
+            .iter()
+            .zip(adapter_samples)
+            .map(|(series, (stats, samples))| AdapterSamples {
+                series: series.clone(),
+                stats,
+                comparison: None,
+                instances: samples.instances,
+                samples: samples.samples,
+            })
+            .collect();
+        Self {
+            name: pass.name.clone(),
+            kind: pass.kind,
+            iteration,
+            duration_ms: pass.duration_ms,
+            frames,
+            adapters,
+        }
+    }
+}
+
+impl RunResults {
+    pub fn new(
+        config: RunConfig,
+        monitor: Option<MonitorDetails>,
+        adapters: Vec<Adapter>,
+        processes: Vec<Process>,
+        passes: Vec<PassResult>,
+        summary: Vec<PassSummary>,
+    ) -> Self {
+        Self {
+            version: RESULTS_VERSION,
+            config,
+            monitor,
+            adapters,
+            processes,
+            passes,
+            summary,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let reader = std::io::BufReader::new(file);
+        // Check the version first so that older files get a clear error
+        // instead of a missing field.
+        let value: serde_json::Value = serde_json::from_reader(reader)?;
+        let version = value.get("version").and_then(|x| x.as_u64());
+        if version != Some(RESULTS_VERSION as u64) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Unsupported results version {} (expected {})",
+                    version
+                        .map(|x| x.to_string())
+                        .unwrap_or_else(|| "<missing>".to_owned()),
+                    RESULTS_VERSION
+                ),
+            ));
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        let mut writer = std::io::BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use crate::{
+    adapter::Adapter,
+    engine::Series,
+    plan::{PassKind, PlannedPass, plan_iterations},
+    process::Process,
+    results::{AdapterSamples, PassResult},
+    sampling::{Sampler, SourceSamples, sample_values},
+    scenario::{PassSpec, Scenario},
+    significance::{Comparison, welch_t_test},
+    sinks::CaptureSink,
+    stats::SampleStats,
+};
+
+/// The summary statistics and the raw samples of each series.
+pub type PassSamples = Vec<(SampleStats, SourceSamples)>;
+
+pub type BoxedCaptureSink<E> = Box<dyn CaptureSink<Error = E>>;
+
+pub fn run_test<S: Sampler>(sampler: &mut S, duration: Duration) -> Result<PassSamples, S::Error> {
+    let samples = sampler.sample_for(duration)?;
+    let mut result = Vec::with_capacity(samples.len());
+    for samples in samples {
+        let stats = SampleStats::from_timed_samples(&samples.samples);
+        result.push((stats, samples));
+    }
+    Ok(result)
+}
+
+/// Formats a value of a series as `0 - 3D - <value> - Adapter`, or as
+/// `CPU - <value>` for series that aren't per adapter. The process is
+/// prepended when more than one was recorded.
+pub fn format_series(
+    adapters: &[Adapter],
+    processes: &[Process],
+    series: &Series,
+    value: &str,
+) -> String {
+    let line = match series.adapter_index {
+        Some(adapter_index) => format!(
+            "{} - {} - {} - {}",
+            adapter_index,
+            series.measurement.label(),
+            value,
+            adapters
+                .get(adapter_index)
+                .map(|x| x.name.as_str())
+                .unwrap_or_default()
+        ),
+        None => format!("{} - {}", series.measurement.label(), value),
+    };
+    match processes.get(series.process_index) {
+        Some(process) if processes.len() > 1 => format!("{} - {}", process, line),
+        _ => line,
+    }
+}
+
+pub fn print_stats(
+    adapters: &[Adapter],
+    processes: &[Process],
+    series: &[Series],
+    adapter_samples: &[(SampleStats, SourceSamples)],
+) {
+    println!("Resource usage:");
+    for (series, (stats, _)) in series.iter().zip(adapter_samples) {
+        let unit = series.measurement.unit();
+        let mean = format!("{:6.2}{}", stats.mean, unit);
+        println!("  {}", format_series(adapters, processes, series, &mean));
+        println!(
+            "      min: {:6.2}{unit}  median: {:6.2}{unit}  max: {:6.2}{unit}",
+            stats.min, stats.median, stats.max
+        );
+        println!(
+            "      p90: {:6.2}{unit}  p95:    {:6.2}{unit}  p99: {:6.2}{unit}",
+            stats.p90, stats.p95, stats.p99
+        );
+        println!(
+            "      time-weighted mean: {:6.2}{}",
+            stats.time_weighted_mean, unit
+        );
+        println!(
+            "      std dev: {:.2}  cv: {:.3}  samples: {}",
+            stats.std_dev, stats.coefficient_of_variation, stats.count
+        );
+    }
+}
+
+/// Formats a comparison, with `unit` following the delta and its interval.
+pub fn format_comparison(comparison: &Comparison, unit: &str) -> String {
+    format!(
+        "{:+6.2}{unit} ({:.0}% CI {:+.2}{unit} to {:+.2}{unit}, p = {:.4}{})",
+        comparison.delta,
+        (1.0 - comparison.alpha) * 100.0,
+        comparison.ci_lower,
+        comparison.ci_upper,
+        comparison.p_value,
+        if comparison.significant {
+            ", significant"
+        } else {
+            ""
+        },
+    )
+}
+
+pub fn print_plan(scenario: &Scenario, plan: &[PlannedPass]) {
+    println!("Pass order:");
+    for iteration in 0..plan_iterations(plan) {
+        let names: Vec<_> = plan
+            .iter()
+            .filter(|pass| pass.iteration == iteration)
+            .map(|pass| scenario.passes[pass.index].display_name())
+            .collect();
+        println!("  {} - {}", iteration + 1, names.join(", "));
+    }
+    println!();
+}
+
+/// Records a single pass, capturing with `sink` if one is provided.
+pub fn run_pass<S: Sampler>(
+    sink: Option<&mut BoxedCaptureSink<S::Error>>,
+    sampler: &mut S,
+    duration: Duration,
+) -> Result<(Option<usize>, PassSamples), S::Error> {
+    if let Some(sink) = sink {
+        sink.start()?;
+        let samples = run_test(sampler, duration)?;
+        let num_frames = sink.stop()?;
+        Ok((Some(num_frames), samples))
+    } else {
+        let samples = run_test(sampler, duration)?;
+        Ok((None, samples))
+    }
+}
+
+/// Records every pass in the plan, in order. `create_sink` is called before
+/// each pass and returns the sink to capture with, or `None` for passes
+/// that don't capture. Each pass runs for its own duration and is followed
+/// by its rest period. The sampler must return its sample vectors in the
+/// order of `series`. The returned passes are compared against the
+/// baseline of their iteration.
+#[allow(clippy::too_many_arguments)]
+pub fn run_plan<S, F>(
+    scenario: &Scenario,
+    plan: &[PlannedPass],
+    sampler: &mut S,
+    mut create_sink: F,
+    adapters: &[Adapter],
+    processes: &[Process],
+    series: &[Series],
+    alpha: f64,
+) -> Result<Vec<PassResult>, S::Error>
+where
+    S: Sampler,
+    F: FnMut(&PassSpec) -> Result<Option<BoxedCaptureSink<S::Error>>, S::Error>,
+{
+    let iterations = plan_iterations(plan);
+    let mut results = Vec::with_capacity(plan.len());
+    for pass in plan {
+        let spec = &scenario.passes[pass.index];
+        if iterations > 1 {
+            println!(
+                "Recording {} (iteration {}/{})...",
+                spec.display_name(),
+                pass.iteration + 1,
+                iterations
+            );
+        } else {
+            println!("Recording {}...", spec.display_name());
+        }
+
+        let mut sink = create_sink(spec)?;
+        let duration = Duration::from_millis(spec.duration_ms);
+        let (num_frames, samples) = run_pass(sink.as_mut(), sampler, duration)?;
+        if let Some(num_frames) = num_frames {
+            println!("Captured {} frames", num_frames);
+        }
+        print_stats(adapters, processes, series, &samples);
+        println!();
+        results.push(PassResult::new(
+            spec,
+            pass.iteration,
+            num_frames,
+            series,
+            samples,
+        ));
+
+        std::thread::sleep(Duration::from_millis(spec.rest_ms));
+    }
+
+    compare_iterations_to_baseline(&mut results, alpha);
+    Ok(results)
+}
+
+/// Compares each capture pass against the first baseline pass of the same
+/// iteration.
+pub fn compare_iterations_to_baseline(passes: &mut [PassResult], alpha: f64) {
+    let mut baselines: Vec<(usize, Vec<AdapterSamples>)> = Vec::new();
+    for pass in passes.iter() {
+        if pass.kind == PassKind::Baseline
+            && !baselines
+                .iter()
+                .any(|(iteration, _)| *iteration == pass.iteration)
+        {
+            baselines.push((pass.iteration, pass.adapters.clone()));
+        }
+    }
+    for pass in passes.iter_mut() {
+        if pass.kind == PassKind::Baseline {
+            continue;
+        }
+        let Some((_, baseline)) = baselines
+            .iter()
+            .find(|(iteration, _)| *iteration == pass.iteration)
+        else {
+            continue;
+        };
+        for (adapter, baseline) in pass.adapters.iter_mut().zip(baseline) {
+            adapter.comparison = welch_t_test(
+                &sample_values(&baseline.samples),
+                &sample_values(&adapter.samples),
+                alpha,
+            );
+        }
+    }
+}
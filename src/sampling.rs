@@ -0,0 +1,427 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// A value read from a source, stamped with the time since the start of the
+/// pass as measured by a monotonic clock.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub elapsed_ms: f64,
+    pub value: f64,
+    /// The values that were summed into `value`, for sources made up of
+    /// several instances. See `SourceSamples::instances`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instances: Vec<f64>,
+}
+
+impl Sample {
+    pub fn new(elapsed: Duration, value: f64) -> Self {
+        Self {
+            elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+            value,
+            instances: Vec::new(),
+        }
+    }
+
+    /// A sample whose value is the sum of the given instance values.
+    pub fn from_instances(elapsed: Duration, instances: Vec<f64>) -> Self {
+        Self {
+            elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+            value: instances.iter().sum(),
+            instances,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.elapsed_ms / 1000.0)
+    }
+}
+
+/// Extracts the values from a set of samples.
+pub fn sample_values(samples: &[Sample]) -> Vec<f64> {
+    samples.iter().map(|x| x.value).collect()
+}
+
+/// Something that can be periodically polled for a utilization value.
+pub trait UtilizationSource {
+    type Error;
+
+    fn start(&mut self) -> Result<(), Self::Error>;
+    fn sample(&mut self) -> Result<f64, Self::Error>;
+    fn close(&mut self) -> Result<(), Self::Error>;
+
+    /// Names the instances that are summed into each sample, for sources
+    /// made up of several counters.
+    fn instance_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The value of each instance at the last sample, in the order of
+    /// `instance_names`.
+    fn instance_values(&self) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+/// The samples recorded from one source.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceSamples {
+    /// Names the per-instance values of each sample, if the source has them.
+    pub instances: Vec<String>,
+    pub samples: Vec<Sample>,
+}
+
+/// Records the samples of each series (e.g. each adapter) over the given
+/// duration.
+pub trait Sampler {
+    type Error;
+
+    fn sample_for(&mut self, duration: Duration) -> Result<Vec<SourceSamples>, Self::Error>;
+}
+
+/// A set of sources (one per series) and the samples collected from them so
+/// far. Series without a source (e.g. memory without an adapter, or an
+/// engine type the adapter doesn't have) are kept as `None` so that the
+/// sample vectors stay aligned with the series list.
+pub struct PerfTrackerBundle<S> {
+    trackers: Vec<Option<S>>,
+    samples: Vec<Vec<Sample>>,
+}
+
+impl<S: UtilizationSource> PerfTrackerBundle<S> {
+    pub fn new(trackers: Vec<Option<S>>) -> Self {
+        let samples = vec![Vec::new(); trackers.len()];
+        Self { trackers, samples }
+    }
+
+    pub fn start(&mut self) -> Result<(), S::Error> {
+        for tracker in self.trackers.iter_mut().flatten() {
+            tracker.start()?;
+        }
+        Ok(())
+    }
+
+    pub fn update_samples(&mut self, elapsed: Duration) -> Result<(), S::Error> {
+        for (tracker, samples) in self.trackers.iter_mut().zip(self.samples.iter_mut()) {
+            if let Some(tracker) = tracker.as_mut() {
+                let value = tracker.sample()?;
+                let instances = tracker.instance_values();
+                if instances.is_empty() {
+                    samples.push(Sample::new(elapsed, value));
+                } else {
+                    samples.push(Sample::from_instances(elapsed, instances));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn drain_samples(&mut self) -> Vec<SourceSamples> {
+        self.trackers
+            .iter()
+            .zip(self.samples.iter_mut())
+            .map(|(tracker, samples)| SourceSamples {
+                instances: tracker
+                    .as_ref()
+                    .map(|x| x.instance_names())
+                    .unwrap_or_default(),
+                samples: std::mem::take(samples),
+            })
+            .collect()
+    }
+
+    pub fn close(&mut self) -> Result<(), S::Error> {
+        for tracker in self.trackers.iter_mut().flatten() {
+            tracker.close()?;
+        }
+        Ok(())
+    }
+}
+
+/// The timer-independent part of a perf session: timestamps each tick
+/// against the start of the session and hands back the samples once the
+/// target duration has elapsed. Timers drift, so the elapsed time is
+/// measured rather than accumulated from the tick length. The next tick is
+/// shortened if a full tick would run past the target.
+pub struct SamplingState<S> {
+    target_length: Duration,
+    current_length: Duration,
+    tick_length: Duration,
+    start_time: Option<Instant>,
+    trackers: PerfTrackerBundle<S>,
+}
+
+impl<S: UtilizationSource> SamplingState<S> {
+    pub fn new(
+        target_length: Duration,
+        tick_length: Duration,
+        trackers: PerfTrackerBundle<S>,
+    ) -> Self {
+        Self {
+            target_length,
+            current_length: Duration::from_secs(0),
+            tick_length,
+            start_time: None,
+            trackers,
+        }
+    }
+
+    pub fn tick_length(&self) -> Duration {
+        self.tick_length
+    }
+
+    /// The length of the next tick, measured from the previous one.
+    pub fn next_tick_length(&self) -> Duration {
+        self.tick_length
+            .min(self.target_length.saturating_sub(self.current_length))
+    }
+
+    pub fn start(&mut self) -> Result<(), S::Error> {
+        self.start_at(Instant::now())
+    }
+
+    pub fn start_at(&mut self, now: Instant) -> Result<(), S::Error> {
+        self.start_time = Some(now);
+        self.current_length = Duration::from_secs(0);
+        self.trackers.start()
+    }
+
+    /// Takes a sample from every source. Returns the samples collected for
+    /// the whole session once the target duration has been reached.
+    pub fn on_tick(&mut self) -> Result<Option<Vec<SourceSamples>>, S::Error> {
+        self.on_tick_at(Instant::now())
+    }
+
+    pub fn on_tick_at(&mut self, now: Instant) -> Result<Option<Vec<SourceSamples>>, S::Error> {
+        self.current_length = self
+            .start_time
+            .map(|start_time| now.saturating_duration_since(start_time))
+            .unwrap_or_default();
+        self.trackers.update_samples(self.current_length)?;
+
+        if self.current_length >= self.target_length {
+            let samples = self.trackers.drain_samples();
+            self.trackers.close()?;
+            Ok(Some(samples))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Runs each session to completion without waiting between ticks, as if
+    /// every tick arrived exactly on time. The sources for each session are
+    /// created by `create_sources`.
+    struct ImmediateSampler<F> {
+        tick_length: Duration,
+        create_sources: F,
+    }
+
+    impl<F> ImmediateSampler<F> {
+        fn new(tick_length: Duration, create_sources: F) -> Self {
+            Self {
+                tick_length,
+                create_sources,
+            }
+        }
+    }
+
+    impl<S, F> Sampler for ImmediateSampler<F>
+    where
+        S: UtilizationSource,
+        F: FnMut() -> Vec<Option<S>>,
+    {
+        type Error = S::Error;
+
+        fn sample_for(&mut self, duration: Duration) -> Result<Vec<SourceSamples>, Self::Error> {
+            let trackers = PerfTrackerBundle::new((self.create_sources)());
+            let mut state = SamplingState::new(duration, self.tick_length, trackers);
+            let mut now = Instant::now();
+            state.start_at(now)?;
+            loop {
+                now += state.next_tick_length();
+                if let Some(samples) = state.on_tick_at(now)? {
+                    return Ok(samples);
+                }
+            }
+        }
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    enum ScriptedSourceError {
+        NotStarted,
+        Exhausted,
+    }
+
+    /// A fake source that replays a fixed sequence of values.
+    struct ScriptedSource {
+        values: VecDeque<f64>,
+        started: bool,
+        closed: bool,
+    }
+
+    impl ScriptedSource {
+        fn new<I: IntoIterator<Item = f64>>(values: I) -> Self {
+            Self {
+                values: values.into_iter().collect(),
+                started: false,
+                closed: false,
+            }
+        }
+
+        fn remaining(&self) -> usize {
+            self.values.len()
+        }
+
+        fn is_closed(&self) -> bool {
+            self.closed
+        }
+    }
+
+    impl UtilizationSource for ScriptedSource {
+        type Error = ScriptedSourceError;
+
+        fn start(&mut self) -> Result<(), Self::Error> {
+            self.started = true;
+            Ok(())
+        }
+
+        fn sample(&mut self) -> Result<f64, Self::Error> {
+            if !self.started || self.closed {
+                return Err(ScriptedSourceError::NotStarted);
+            }
+            self.values
+                .pop_front()
+                .ok_or(ScriptedSourceError::Exhausted)
+        }
+
+        fn close(&mut self) -> Result<(), Self::Error> {
+            self.closed = true;
+            Ok(())
+        }
+    }
+
+    fn scripted_sources(values: &[f64], count: usize) -> Vec<Option<ScriptedSource>> {
+        (0..count)
+            .map(|_| Some(ScriptedSource::new(values.iter().copied())))
+            .collect()
+    }
+
+    #[test]
+    fn immediate_sampler_takes_one_sample_per_tick() {
+        let mut sampler = ImmediateSampler::new(Duration::from_millis(500), || {
+            scripted_sources(&[1.0, 2.0, 3.0, 4.0], 2)
+        });
+        let samples = sampler.sample_for(Duration::from_secs(2)).unwrap();
+        assert_eq!(samples.len(), 2);
+        for source in &samples {
+            assert_eq!(sample_values(&source.samples), [1.0, 2.0, 3.0, 4.0]);
+            let elapsed: Vec<_> = source.samples.iter().map(|x| x.elapsed_ms).collect();
+            assert_eq!(elapsed, [500.0, 1000.0, 1500.0, 2000.0]);
+        }
+    }
+
+    #[test]
+    fn uneven_interval_shortens_the_last_tick() {
+        let mut sampler = ImmediateSampler::new(Duration::from_millis(300), || {
+            scripted_sources(&[1.0, 2.0, 3.0, 4.0, 5.0], 1)
+        });
+        let samples = sampler.sample_for(Duration::from_secs(1)).unwrap();
+        let elapsed: Vec<_> = samples[0].samples.iter().map(|x| x.elapsed_ms).collect();
+        // The session ends on time rather than at the next full tick
+        assert_eq!(elapsed, [300.0, 600.0, 900.0, 1000.0]);
+    }
+
+    #[test]
+    fn next_tick_length_never_runs_past_the_target() {
+        let trackers = PerfTrackerBundle::new(vec![Some(ScriptedSource::new([0.0; 4]))]);
+        let mut state = SamplingState::new(
+            Duration::from_millis(1000),
+            Duration::from_millis(400),
+            trackers,
+        );
+        let start = Instant::now();
+        state.start_at(start).unwrap();
+        assert_eq!(state.next_tick_length(), Duration::from_millis(400));
+        assert!(
+            state
+                .on_tick_at(start + Duration::from_millis(400))
+                .unwrap()
+                .is_none()
+        );
+        // The timer fired late, so the remaining time is shorter
+        assert!(
+            state
+                .on_tick_at(start + Duration::from_millis(850))
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(state.next_tick_length(), Duration::from_millis(150));
+        let samples = state
+            .on_tick_at(start + Duration::from_millis(1000))
+            .unwrap()
+            .unwrap();
+        let elapsed: Vec<_> = samples[0].samples.iter().map(|x| x.elapsed_ms).collect();
+        assert_eq!(elapsed, [400.0, 850.0, 1000.0]);
+    }
+
+    #[test]
+    fn finishing_drains_and_closes_the_sources() {
+        let trackers =
+            PerfTrackerBundle::new(vec![Some(ScriptedSource::new([5.0, 6.0, 7.0])), None]);
+        let mut state = SamplingState::new(
+            Duration::from_millis(200),
+            Duration::from_millis(100),
+            trackers,
+        );
+        let start = Instant::now();
+        state.start_at(start).unwrap();
+        state
+            .on_tick_at(start + Duration::from_millis(100))
+            .unwrap();
+        let samples = state
+            .on_tick_at(start + Duration::from_millis(200))
+            .unwrap()
+            .unwrap();
+        // Missing sources keep their place with no samples
+        assert_eq!(samples.len(), 2);
+        assert_eq!(sample_values(&samples[0].samples), [5.0, 6.0]);
+        assert!(samples[1].samples.is_empty());
+
+        let source = state.trackers.trackers[0].as_ref().unwrap();
+        assert!(source.is_closed());
+        assert_eq!(source.remaining(), 1);
+        assert!(state.trackers.drain_samples()[0].samples.is_empty());
+    }
+
+    #[test]
+    fn errors_from_sources_are_returned() {
+        let trackers = PerfTrackerBundle::new(vec![Some(ScriptedSource::new([1.0]))]);
+        let mut state = SamplingState::new(
+            Duration::from_millis(300),
+            Duration::from_millis(100),
+            trackers,
+        );
+        let start = Instant::now();
+        assert_eq!(
+            state.on_tick_at(start).unwrap_err(),
+            ScriptedSourceError::NotStarted
+        );
+        state.start_at(start).unwrap();
+        state
+            .on_tick_at(start + Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(
+            state
+                .on_tick_at(start + Duration::from_millis(200))
+                .unwrap_err(),
+            ScriptedSourceError::Exhausted
+        );
+    }
+}
@@ -0,0 +1,426 @@
+use std::{fmt::Display, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::plan::{PassKind, PassOrder};
+
+/// A reproducible test plan: which passes to run, how long, and with what
+/// options. Scenarios can be loaded from TOML or JSON files and must be
+/// validated before use.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default = "default_iterations")]
+    pub iterations: usize,
+    #[serde(default = "default_order")]
+    pub order: PassOrder,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default = "default_sample_interval_ms")]
+    pub sample_interval_ms: u64,
+    #[serde(default)]
+    pub workload: WorkloadSettings,
+    pub passes: Vec<PassSpec>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PassSpec {
+    pub kind: PassKind,
+    /// Identifies the pass in output, results files and thresholds. Defaults
+    /// to the name of the kind, so passes of the same kind need explicit
+    /// names.
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_duration_ms")]
+    pub duration_ms: u64,
+    /// How long to wait after the pass before starting the next one.
+    #[serde(default = "default_rest_ms")]
+    pub rest_ms: u64,
+    #[serde(default)]
+    pub sink: SinkOptions,
+}
+
+/// Options for the capture sink. These only apply to WGC passes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SinkOptions {
+    /// Sets the DirtyRegionMode to ReportAndRender.
+    pub use_dirty_rects: bool,
+    pub capture_cursor: bool,
+    pub min_update_interval_ms: u64,
+    /// The number of buffers in the frame pool, at most `MAX_BUFFER_COUNT`.
+    pub buffer_count: u32,
+}
+
+/// The most buffers a frame pool may have. Each buffer holds a whole frame
+/// of the monitor.
+pub const MAX_BUFFER_COUNT: u32 = 64;
+
+/// The dummy content that is animated on the monitor during each pass.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkloadSettings {
+    /// The width and height of the window, in effective (96 DPI) pixels.
+    pub window_size: u32,
+    pub animate: bool,
+    /// How long one rotation of the content takes.
+    pub animation_period_ms: u64,
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl Display for ScenarioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScenarioError::Io(error) => write!(f, "Could not read the scenario: {}", error),
+            ScenarioError::Parse(error) => write!(f, "Could not parse the scenario: {}", error),
+            ScenarioError::Invalid(error) => write!(f, "Invalid scenario: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl From<std::io::Error> for ScenarioError {
+    fn from(value: std::io::Error) -> Self {
+        ScenarioError::Io(value)
+    }
+}
+
+fn default_iterations() -> usize {
+    1
+}
+
+fn default_order() -> PassOrder {
+    PassOrder::Fixed
+}
+
+fn default_sample_interval_ms() -> u64 {
+    500
+}
+
+fn default_duration_ms() -> u64 {
+    5000
+}
+
+fn default_rest_ms() -> u64 {
+    1000
+}
+
+impl Default for SinkOptions {
+    fn default() -> Self {
+        Self {
+            use_dirty_rects: false,
+            capture_cursor: false,
+            min_update_interval_ms: 0,
+            buffer_count: 3,
+        }
+    }
+}
+
+impl Default for WorkloadSettings {
+    fn default() -> Self {
+        Self {
+            window_size: 500,
+            animate: true,
+            animation_period_ms: 3000,
+        }
+    }
+}
+
+impl PassSpec {
+    pub fn new(kind: PassKind, duration_ms: u64, rest_ms: u64) -> Self {
+        Self {
+            kind,
+            name: kind.name().to_owned(),
+            duration_ms,
+            rest_ms,
+            sink: SinkOptions::default(),
+        }
+    }
+
+    /// The name used in console output.
+    pub fn display_name(&self) -> &str {
+        pass_display_name(&self.name, self.kind)
+    }
+}
+
+/// Uses the kind's display name for passes that kept their default name.
+pub fn pass_display_name(name: &str, kind: PassKind) -> &str {
+    if name == kind.name() {
+        kind.display_name()
+    } else {
+        name
+    }
+}
+
+impl Scenario {
+    /// Loads and validates a scenario. Files ending in `.json` are read as
+    /// JSON, everything else as TOML.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ScenarioError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|x| x.eq_ignore_ascii_case("json"));
+        let scenario: Self = if is_json {
+            serde_json::from_str(&text).map_err(|x| ScenarioError::Parse(x.to_string()))?
+        } else {
+            toml::from_str(&text).map_err(|x| ScenarioError::Parse(x.to_string()))?
+        };
+        scenario.validate()
+    }
+
+    /// Fills in default pass names and checks that the scenario can be run.
+    pub fn validate(mut self) -> Result<Self, ScenarioError> {
+        let invalid = |message: String| Err(ScenarioError::Invalid(message));
+        if self.passes.is_empty() {
+            return invalid("There must be at least one pass".to_owned());
+        }
+        if self.iterations == 0 {
+            return invalid("There must be at least one iteration".to_owned());
+        }
+        if self.sample_interval_ms == 0 {
+            return invalid("The sample interval must be at least 1 ms".to_owned());
+        }
+        if self.workload.window_size == 0 {
+            return invalid("The window size must be non-zero".to_owned());
+        }
+        if self.workload.animate && self.workload.animation_period_ms == 0 {
+            return invalid("The animation period must be non-zero".to_owned());
+        }
+
+        for (i, pass) in self.passes.iter_mut().enumerate() {
+            if pass.name.is_empty() {
+                pass.name = pass.kind.name().to_owned();
+            }
+            if pass.duration_ms == 0 {
+                return invalid(format!("Pass {} ({}) has no duration", i, pass.name));
+            }
+            if pass.kind != PassKind::Wgc && pass.sink != SinkOptions::default() {
+                return invalid(format!(
+                    "Pass {} ({}) sets sink options, which only apply to WGC passes",
+                    i, pass.name
+                ));
+            }
+            if pass.sink.buffer_count == 0 {
+                return invalid(format!(
+                    "Pass {} ({}) needs at least one buffer",
+                    i, pass.name
+                ));
+            }
+            if pass.sink.buffer_count > MAX_BUFFER_COUNT {
+                return invalid(format!(
+                    "Pass {} ({}) has more than {} buffers",
+                    i, pass.name, MAX_BUFFER_COUNT
+                ));
+            }
+        }
+        for (i, pass) in self.passes.iter().enumerate() {
+            if self.passes[..i].iter().any(|x| x.name == pass.name) {
+                return invalid(format!(
+                    "More than one pass is named \"{}\". Give passes of the same kind unique names.",
+                    pass.name
+                ));
+            }
+        }
+
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TempDir;
+
+    use super::*;
+
+    const TOML: &str = r#"
+name = "dirty rects"
+iterations = 2
+order = "abba"
+sample_interval_ms = 250
+
+[[passes]]
+kind = "baseline"
+duration_ms = 2000
+
+[[passes]]
+kind = "wgc"
+name = "wgc-dirty"
+sink = { use_dirty_rects = true, buffer_count = 2 }
+
+[[passes]]
+kind = "wgc"
+"#;
+
+    const JSON: &str = r#"{
+    "name": "dirty rects",
+    "iterations": 2,
+    "order": "abba",
+    "sample_interval_ms": 250,
+    "passes": [
+        { "kind": "baseline", "duration_ms": 2000 },
+        { "kind": "wgc", "name": "wgc-dirty", "sink": { "use_dirty_rects": true, "buffer_count": 2 } },
+        { "kind": "wgc" }
+    ]
+}"#;
+
+    fn parse(toml: &str) -> Result<Scenario, ScenarioError> {
+        toml::from_str::<Scenario>(toml)
+            .map_err(|x| ScenarioError::Parse(x.to_string()))?
+            .validate()
+    }
+
+    fn assert_invalid(toml: &str, message: &str) {
+        match parse(toml) {
+            Err(ScenarioError::Invalid(error)) => {
+                assert!(error.contains(message), "{}", error)
+            }
+            result => panic!("Expected \"{}\", got {:?}", message, result),
+        }
+    }
+
+    #[test]
+    fn loads_the_same_plan_from_toml_and_json() {
+        let dir = TempDir::new("scenario");
+        let toml_path = dir.path.join("plan.toml");
+        let json_path = dir.path.join("plan.JSON");
+        std::fs::write(&toml_path, TOML).unwrap();
+        std::fs::write(&json_path, JSON).unwrap();
+
+        let scenario = Scenario::load(&toml_path).unwrap();
+        assert_eq!(scenario, Scenario::load(&json_path).unwrap());
+        assert_eq!(scenario.name.as_deref(), Some("dirty rects"));
+        assert_eq!(scenario.iterations, 2);
+        assert_eq!(scenario.order, PassOrder::Abba);
+        assert_eq!(scenario.seed, None);
+        assert_eq!(scenario.sample_interval_ms, 250);
+        assert_eq!(scenario.workload, WorkloadSettings::default());
+
+        let passes: Vec<_> = scenario
+            .passes
+            .iter()
+            .map(|x| (x.kind, x.name.as_str(), x.duration_ms, x.rest_ms))
+            .collect();
+        assert_eq!(
+            passes,
+            [
+                (PassKind::Baseline, "baseline", 2000, 1000),
+                (PassKind::Wgc, "wgc-dirty", 5000, 1000),
+                (PassKind::Wgc, "wgc", 5000, 1000),
+            ]
+        );
+        assert_eq!(
+            scenario.passes[1].sink,
+            SinkOptions {
+                use_dirty_rects: true,
+                buffer_count: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unparsable_files_are_parse_errors() {
+        let dir = TempDir::new("scenario-parse");
+        let path = dir.path.join("plan.json");
+        std::fs::write(&path, TOML).unwrap();
+        assert!(matches!(
+            Scenario::load(&path),
+            Err(ScenarioError::Parse(_))
+        ));
+        assert!(matches!(
+            Scenario::load(dir.path.join("missing.toml")),
+            Err(ScenarioError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        for toml in [
+            "itterations = 2\n[[passes]]\nkind = \"baseline\"",
+            "[[passes]]\nkind = \"baseline\"\nduration = 10",
+            "[[passes]]\nkind = \"wgc\"\nsink = { dirty_rects = true }",
+            "[workload]\nsize = 10\n[[passes]]\nkind = \"baseline\"",
+        ] {
+            assert!(
+                matches!(parse(toml), Err(ScenarioError::Parse(_))),
+                "{}",
+                toml
+            );
+        }
+    }
+
+    #[test]
+    fn passes_need_unique_names() {
+        assert_invalid(
+            "[[passes]]\nkind = \"wgc\"\n[[passes]]\nkind = \"wgc\"",
+            "More than one pass is named \"wgc\"",
+        );
+        // Default names count too
+        assert_invalid(
+            "[[passes]]\nkind = \"dda\"\n[[passes]]\nkind = \"wgc\"\nname = \"dda\"",
+            "More than one pass is named \"dda\"",
+        );
+    }
+
+    #[test]
+    fn sink_options_only_apply_to_wgc_passes() {
+        assert_invalid(
+            "[[passes]]\nkind = \"dda\"\nsink = { capture_cursor = true }",
+            "Pass 0 (dda) sets sink options",
+        );
+        assert_invalid(
+            "[[passes]]\nkind = \"baseline\"\nsink = { buffer_count = 2 }",
+            "Pass 0 (baseline) sets sink options",
+        );
+    }
+
+    #[test]
+    fn rejects_empty_plans_and_zero_lengths() {
+        assert_invalid("passes = []", "at least one pass");
+        assert_invalid(
+            "iterations = 0\n[[passes]]\nkind = \"baseline\"",
+            "at least one iteration",
+        );
+        assert_invalid(
+            "sample_interval_ms = 0\n[[passes]]\nkind = \"baseline\"",
+            "sample interval",
+        );
+        assert_invalid(
+            "[[passes]]\nkind = \"baseline\"\nduration_ms = 0",
+            "Pass 0 (baseline) has no duration",
+        );
+        assert_invalid(
+            "[workload]\nanimation_period_ms = 0\n[[passes]]\nkind = \"baseline\"",
+            "animation period",
+        );
+        // The period doesn't matter without animation
+        assert!(parse(
+            "[workload]\nanimate = false\nanimation_period_ms = 0\n[[passes]]\nkind = \"baseline\""
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn buffer_counts_are_bounded() {
+        assert_invalid(
+            "[[passes]]\nkind = \"wgc\"\nsink = { buffer_count = 0 }",
+            "needs at least one buffer",
+        );
+        assert_invalid(
+            "[[passes]]\nkind = \"wgc\"\nsink = { buffer_count = 4294967295 }",
+            "more than 64 buffers",
+        );
+        assert!(parse("[[passes]]\nkind = \"wgc\"\nsink = { buffer_count = 64 }").is_ok());
+    }
+}
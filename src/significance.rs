@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+
+use crate::stats::SampleStats;
+
+/// The difference between a candidate pass and the baseline pass, as
+/// computed by Welch's t-test.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+    /// The candidate mean minus the baseline mean.
+    pub delta: f64,
+    /// The lower bound of the (1 - alpha) confidence interval of `delta`.
+    pub ci_lower: f64,
+    /// The upper bound of the (1 - alpha) confidence interval of `delta`.
+    pub ci_upper: f64,
+    pub t_statistic: f64,
+    pub degrees_of_freedom: f64,
+    /// The two-sided p-value.
+    pub p_value: f64,
+    pub alpha: f64,
+    pub significant: bool,
+}
+
+/// Runs Welch's t-test on two sets of samples. Returns `None` if either set
+/// has fewer than two samples.
+pub fn welch_t_test(baseline: &[f64], candidate: &[f64], alpha: f64) -> Option<Comparison> {
+    if baseline.len() < 2 || candidate.len() < 2 {
+        return None;
+    }
+    let baseline = SampleStats::from_samples(baseline);
+    let candidate = SampleStats::from_samples(candidate);
+    Some(welch_t_test_from_stats(&baseline, &candidate, alpha))
+}
+
+/// Runs Welch's t-test using precomputed statistics. Both sets of statistics
+/// must describe at least two samples.
+fn welch_t_test_from_stats(
+    baseline: &SampleStats,
+    candidate: &SampleStats,
+    alpha: f64,
+) -> Comparison {
+    let delta = candidate.mean - baseline.mean;
+    let baseline_variance = baseline.std_dev * baseline.std_dev / baseline.count as f64;
+    let candidate_variance = candidate.std_dev * candidate.std_dev / candidate.count as f64;
+    let variance = baseline_variance + candidate_variance;
+
+    if variance == 0.0 {
+        // Both passes were perfectly flat, so any difference is exact.
+        let p_value = if delta == 0.0 { 1.0 } else { 0.0 };
+        return Comparison {
+            delta,
+            ci_lower: delta,
+            ci_upper: delta,
+            t_statistic: if delta == 0.0 {
+                0.0
+            } else {
+                delta.signum() * f64::INFINITY
+            },
+            degrees_of_freedom: (baseline.count + candidate.count - 2) as f64,
+            p_value,
+            alpha,
+            significant: p_value < alpha,
+        };
+    }
+
+    let standard_error = variance.sqrt();
+    let t_statistic = delta / standard_error;
+    // Welch-Satterthwaite equation
+    let degrees_of_freedom = (variance * variance)
+        / ((baseline_variance * baseline_variance) / (baseline.count - 1) as f64
+            + (candidate_variance * candidate_variance) / (candidate.count - 1) as f64);
+    let p_value = students_t_two_sided_p(t_statistic, degrees_of_freedom);
+    let critical_value = students_t_critical_value(alpha, degrees_of_freedom);
+    let margin = critical_value * standard_error;
+
+    Comparison {
+        delta,
+        ci_lower: delta - margin,
+        ci_upper: delta + margin,
+        t_statistic,
+        degrees_of_freedom,
+        p_value,
+        alpha,
+        significant: p_value < alpha,
+    }
+}
+
+/// The probability of observing a t statistic at least as extreme as `t`
+/// under Student's t-distribution.
+pub fn students_t_two_sided_p(t: f64, degrees_of_freedom: f64) -> f64 {
+    if t.is_infinite() {
+        return 0.0;
+    }
+    let x = degrees_of_freedom / (degrees_of_freedom + t * t);
+    regularized_incomplete_beta(x, degrees_of_freedom / 2.0, 0.5)
+}
+
+/// Finds the positive t such that the two-sided p-value equals `alpha`.
+pub fn students_t_critical_value(alpha: f64, degrees_of_freedom: f64) -> f64 {
+    let mut low = 0.0;
+    let mut high = 1.0;
+    while students_t_two_sided_p(high, degrees_of_freedom) > alpha && high < 1e6 {
+        high *= 2.0;
+    }
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        if students_t_two_sided_p(mid, degrees_of_freedom) > alpha {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+fn ln_gamma(x: f64) -> f64 {
+    // Lanczos approximation (g = 7, n = 9)
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // Reflection formula
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += coefficient / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln();
+    let front = ln_front.exp();
+    // The continued fraction converges quickly only on one side of the mean,
+    // so use the symmetry relation on the other.
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    // Modified Lentz's method
+    const EPSILON: f64 = 1e-15;
+    const TINY: f64 = 1e-300;
+    const MAX_ITERATIONS: usize = 300;
+
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut result = d;
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        // Even step
+        let numerator = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 + numerator * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + numerator / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        result *= d * c;
+
+        // Odd step
+        let numerator = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 + numerator * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + numerator / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        result *= delta;
+
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn matches_reference_values() {
+        assert_close(ln_gamma(5.0), 24f64.ln(), 1e-12);
+        assert_close(ln_gamma(0.5), std::f64::consts::PI.sqrt().ln(), 1e-12);
+        assert_close(students_t_two_sided_p(2.0, 10.0), 0.0734, 1e-4);
+        assert_close(students_t_two_sided_p(0.0, 10.0), 1.0, 1e-12);
+        assert_close(students_t_two_sided_p(-2.0, 10.0), 0.0734, 1e-4);
+        assert_close(students_t_critical_value(0.05, 10.0), 2.228, 1e-3);
+        assert_close(students_t_critical_value(0.01, 5.0), 4.032, 1e-3);
+        // Approaches the normal distribution
+        assert_close(students_t_critical_value(0.05, 1e6), 1.960, 1e-3);
+    }
+
+    #[test]
+    fn computes_welchs_t_test() {
+        // Means 3 and 5 with a variance of 2.5 each: t = 2 with 8 degrees of
+        // freedom
+        let comparison =
+            welch_t_test(&[1.0, 2.0, 3.0, 4.0, 5.0], &[3.0, 4.0, 5.0, 6.0, 7.0], 0.05).unwrap();
+        assert_close(comparison.delta, 2.0, 1e-12);
+        assert_close(comparison.t_statistic, 2.0, 1e-12);
+        assert_close(comparison.degrees_of_freedom, 8.0, 1e-12);
+        assert_close(comparison.p_value, 0.0805, 1e-4);
+        assert_close(comparison.ci_lower, 2.0 - 2.306, 1e-3);
+        assert_close(comparison.ci_upper, 2.0 + 2.306, 1e-3);
+        assert!(!comparison.significant);
+    }
+
+    #[test]
+    fn distributions_that_differ_are_significant() {
+        let baseline = [10.0, 11.0, 9.0, 10.5, 9.5, 10.0, 11.0, 9.0];
+        let candidate = [20.0, 21.0, 19.0, 20.5, 19.5, 20.0, 21.0, 19.0];
+        let comparison = welch_t_test(&baseline, &candidate, 0.05).unwrap();
+        assert_close(comparison.delta, 10.0, 1e-12);
+        assert!(comparison.p_value < 1e-6, "{}", comparison.p_value);
+        assert!(comparison.ci_lower > 0.0);
+        assert!(comparison.significant);
+
+        // The same samples in another order
+        let shuffled = [10.0, 9.0, 11.0, 9.5, 10.5, 11.0, 10.0, 9.0];
+        let comparison = welch_t_test(&baseline, &shuffled, 0.05).unwrap();
+        assert_close(comparison.delta, 0.0, 1e-12);
+        assert_close(comparison.p_value, 1.0, 1e-9);
+        assert!(comparison.ci_lower < 0.0 && comparison.ci_upper > 0.0);
+        assert!(!comparison.significant);
+    }
+
+    #[test]
+    fn flat_passes_are_compared_exactly() {
+        let comparison = welch_t_test(&[5.0, 5.0, 5.0], &[7.0, 7.0], 0.05).unwrap();
+        assert_eq!(comparison.delta, 2.0);
+        assert_eq!(comparison.ci_lower, 2.0);
+        assert_eq!(comparison.ci_upper, 2.0);
+        assert_eq!(comparison.t_statistic, f64::INFINITY);
+        assert_eq!(comparison.degrees_of_freedom, 3.0);
+        assert_eq!(comparison.p_value, 0.0);
+        assert!(comparison.significant);
+
+        let comparison = welch_t_test(&[5.0, 5.0], &[5.0, 5.0], 0.05).unwrap();
+        assert_eq!(comparison.t_statistic, 0.0);
+        assert_eq!(comparison.p_value, 1.0);
+        assert!(!comparison.significant);
+    }
+
+    #[test]
+    fn needs_two_samples_on_each_side() {
+        assert_eq!(welch_t_test(&[1.0], &[1.0, 2.0], 0.05), None);
+        assert_eq!(welch_t_test(&[1.0, 2.0], &[], 0.05), None);
+    }
+}
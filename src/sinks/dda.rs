@@ -11,7 +11,7 @@ use windows::{
     core::Result,
 };
 
-use crate::windows_utils::event::Event;
+use crate::{error::WgcPerfError, windows_utils::event::Event};
 
 use super::CaptureSink;
 
@@ -43,9 +43,15 @@ impl DdaCaptureSink {
 }
 
 impl CaptureSink for DdaCaptureSink {
-    fn start(&mut self) -> windows::core::Result<()> {
-        if self.duplication.is_none() && !self.stop_event.is_signaled()? {
-            let duplication = unsafe { self.output.DuplicateOutput(&self.d3d_device)? };
+    type Error = WgcPerfError;
+
+    fn start(&mut self) -> std::result::Result<(), WgcPerfError> {
+        let start_error = |error: windows::core::Error| {
+            WgcPerfError::capture("Could not start the DDA capture", error)
+        };
+        if self.duplication.is_none() && !self.stop_event.is_signaled().map_err(start_error)? {
+            let duplication =
+                unsafe { self.output.DuplicateOutput(&self.d3d_device) }.map_err(start_error)?;
             let capture_thread = std::thread::spawn({
                 let duplication = DuplicationSmuggler(duplication.clone());
                 let event = self.stop_event.clone();
@@ -74,10 +80,18 @@ impl CaptureSink for DdaCaptureSink {
         Ok(())
     }
 
-    fn stop(&mut self) -> windows::core::Result<usize> {
+    fn stop(&mut self) -> std::result::Result<usize, WgcPerfError> {
         let num_frames = if let Some(thread) = self.capture_thread.take() {
-            self.stop_event.signal()?;
-            thread.join().unwrap()?;
+            self.stop_event
+                .signal()
+                .map_err(|error| WgcPerfError::capture("Could not stop the DDA capture", error))?;
+            thread
+                .join()
+                .map_err(|_| WgcPerfError::Capture {
+                    context: "The DDA capture thread panicked".to_owned(),
+                    source: None,
+                })?
+                .map_err(|error| WgcPerfError::capture("DDA capture failed", error))?;
             self.duplication = None;
             self.num_frames.load(std::sync::atomic::Ordering::SeqCst)
         } else {
@@ -1,7 +1,11 @@
 pub trait CaptureSink {
-    fn start(&mut self) -> windows::core::Result<()>;
-    fn stop(&mut self) -> windows::core::Result<usize>;
+    type Error;
+
+    fn start(&mut self) -> Result<(), Self::Error>;
+    fn stop(&mut self) -> Result<usize, Self::Error>;
 }
 
+#[cfg(windows)]
 pub mod dda;
+#[cfg(windows)]
 pub mod wgc;
@@ -19,7 +19,9 @@ use windows::{
     core::Result,
 };
 
-use crate::windows_utils::d3d::create_direct3d_device;
+use crate::{
+    error::WgcPerfError, scenario::SinkOptions, windows_utils::d3d::create_direct3d_device,
+};
 
 use super::CaptureSink;
 
@@ -34,7 +36,7 @@ impl WgcCaptureSink {
     pub fn new(
         d3d_device: &ID3D11Device,
         monitor: HMONITOR,
-        use_dirty_rects: bool,
+        options: &SinkOptions,
     ) -> Result<Self> {
         let device = create_direct3d_device(d3d_device)?;
         let item = create_capture_item_for_monitor(monitor)?;
@@ -42,7 +44,7 @@ impl WgcCaptureSink {
         let frame_pool = Direct3D11CaptureFramePool::CreateFreeThreaded(
             &device,
             DirectXPixelFormat::B8G8R8A8UIntNormalized,
-            3,
+            options.buffer_count as i32,
             item.Size()?,
         )?;
         frame_pool.FrameArrived(&TypedEventHandler::<Direct3D11CaptureFramePool, _>::new({
@@ -57,13 +59,14 @@ impl WgcCaptureSink {
         }))?;
         let session = frame_pool.CreateCaptureSession(&item)?;
         session.SetIsBorderRequired(false)?;
-        session.SetIsCursorCaptureEnabled(false)?;
-        if use_dirty_rects {
+        session.SetIsCursorCaptureEnabled(options.capture_cursor)?;
+        if options.use_dirty_rects {
             session.SetDirtyRegionMode(GraphicsCaptureDirtyRegionMode::ReportAndRender)?;
         }
         // There's a bug where setting 0 won't work until we set something non-zero first
         session.SetMinUpdateInterval(Duration::from_millis(1).into())?;
-        session.SetMinUpdateInterval(Duration::from_millis(0).into())?;
+        session
+            .SetMinUpdateInterval(Duration::from_millis(options.min_update_interval_ms).into())?;
         Ok(Self {
             _item: item,
             session,
@@ -74,14 +77,19 @@ impl WgcCaptureSink {
 }
 
 impl CaptureSink for WgcCaptureSink {
-    fn start(&mut self) -> Result<()> {
-        self.session.StartCapture()?;
-        Ok(())
+    type Error = WgcPerfError;
+
+    fn start(&mut self) -> std::result::Result<(), WgcPerfError> {
+        self.session
+            .StartCapture()
+            .map_err(|error| WgcPerfError::capture("Could not start the WGC capture", error))
     }
 
-    fn stop(&mut self) -> Result<usize> {
-        self.session.Close()?;
-        self.frame_pool.Close()?;
+    fn stop(&mut self) -> std::result::Result<usize, WgcPerfError> {
+        self.session
+            .Close()
+            .and_then(|_| self.frame_pool.Close())
+            .map_err(|error| WgcPerfError::capture("Could not stop the WGC capture", error))?;
         let num_frames = self.num_frames.load(std::sync::atomic::Ordering::SeqCst);
         Ok(num_frames)
     }
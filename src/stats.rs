@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sampling::{Sample, sample_values};
+
+/// Summary statistics for a set of samples. Empty sample sets produce all
+/// zeros.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SampleStats {
+    pub count: usize,
+    pub mean: f64,
+    /// The mean weighted by how much time each sample covers. Equal to
+    /// `mean` for untimed samples.
+    pub time_weighted_mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    /// The sample (n - 1) standard deviation.
+    pub std_dev: f64,
+    /// The standard deviation relative to the mean, or zero if the mean is zero.
+    pub coefficient_of_variation: f64,
+}
+
+impl SampleStats {
+    pub fn from_samples(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let std_dev = if count > 1 {
+            let sum_of_squares: f64 = sorted.iter().map(|x| (x - mean) * (x - mean)).sum();
+            (sum_of_squares / (count - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        let coefficient_of_variation = if mean != 0.0 { std_dev / mean } else { 0.0 };
+
+        Self {
+            count,
+            mean,
+            time_weighted_mean: mean,
+            min: sorted[0],
+            max: sorted[count - 1],
+            median: percentile(&sorted, 50.0),
+            p90: percentile(&sorted, 90.0),
+            p95: percentile(&sorted, 95.0),
+            p99: percentile(&sorted, 99.0),
+            std_dev,
+            coefficient_of_variation,
+        }
+    }
+}
+
+impl SampleStats {
+    /// Computes statistics for timestamped samples. Each sample is treated as
+    /// covering the time since the previous sample (or the start of the pass).
+    pub fn from_timed_samples(samples: &[Sample]) -> Self {
+        let mut stats = Self::from_samples(&sample_values(samples));
+        if let Some(time_weighted_mean) = time_weighted_mean(samples) {
+            stats.time_weighted_mean = time_weighted_mean;
+        }
+        stats
+    }
+}
+
+/// Averages timestamped samples, weighting each by the time since the
+/// previous sample. Returns `None` if the samples cover no time.
+pub fn time_weighted_mean(samples: &[Sample]) -> Option<f64> {
+    let mut previous_elapsed = 0.0;
+    let mut weighted_sum = 0.0;
+    for sample in samples {
+        let weight = (sample.elapsed_ms - previous_elapsed).max(0.0);
+        weighted_sum += sample.value * weight;
+        previous_elapsed = sample.elapsed_ms.max(previous_elapsed);
+    }
+    if previous_elapsed > 0.0 {
+        Some(weighted_sum / previous_elapsed)
+    } else {
+        None
+    }
+}
+
+/// Computes a percentile (0-100) of an already sorted, non-empty slice,
+/// interpolating linearly between the closest ranks.
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    let rank = (percentile / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let weight = rank - lower as f64;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn summarizes_a_known_vector() {
+        let stats = SampleStats::from_samples(&[4.0, 1.0, 3.0, 2.0, 5.0]);
+        assert_eq!(stats.count, 5);
+        assert_close(stats.mean, 3.0);
+        assert_close(stats.time_weighted_mean, 3.0);
+        assert_close(stats.min, 1.0);
+        assert_close(stats.max, 5.0);
+        assert_close(stats.median, 3.0);
+        // Interpolated between the 4th and 5th values
+        assert_close(stats.p90, 4.6);
+        assert_close(stats.p95, 4.8);
+        assert_close(stats.p99, 4.96);
+        assert_close(stats.std_dev, 2.5f64.sqrt());
+        assert_close(stats.coefficient_of_variation, 2.5f64.sqrt() / 3.0);
+    }
+
+    #[test]
+    fn medians_of_even_counts_are_interpolated() {
+        let stats = SampleStats::from_samples(&[10.0, 40.0, 20.0, 30.0]);
+        assert_close(stats.median, 25.0);
+        assert_close(stats.p90, 37.0);
+    }
+
+    #[test]
+    fn single_and_zero_samples_have_no_spread() {
+        let stats = SampleStats::from_samples(&[7.0]);
+        assert_eq!(stats.count, 1);
+        assert_close(stats.median, 7.0);
+        assert_close(stats.p99, 7.0);
+        assert_close(stats.std_dev, 0.0);
+
+        let stats = SampleStats::from_samples(&[0.0, 0.0, 0.0]);
+        assert_close(stats.coefficient_of_variation, 0.0);
+    }
+
+    fn timed(points: &[(f64, f64)]) -> Vec<Sample> {
+        points
+            .iter()
+            .map(|(elapsed_ms, value)| Sample {
+                elapsed_ms: *elapsed_ms,
+                value: *value,
+                instances: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn samples_are_weighted_by_the_time_they_cover() {
+        // 100ms at 10%, 300ms at 50% and 100ms at 0%
+        let samples = timed(&[(100.0, 10.0), (400.0, 50.0), (500.0, 0.0)]);
+        assert_close(time_weighted_mean(&samples).unwrap(), 32.0);
+
+        let stats = SampleStats::from_timed_samples(&samples);
+        assert_close(stats.mean, 20.0);
+        assert_close(stats.time_weighted_mean, 32.0);
+    }
+
+    #[test]
+    fn samples_covering_no_time_have_no_time_weighted_mean() {
+        assert_eq!(time_weighted_mean(&[]), None);
+        let samples = timed(&[(0.0, 10.0), (0.0, 30.0)]);
+        assert_eq!(time_weighted_mean(&samples), None);
+
+        // Falls back to the plain mean
+        let stats = SampleStats::from_timed_samples(&samples);
+        assert_close(stats.time_weighted_mean, 20.0);
+    }
+
+    #[test]
+    fn out_of_order_samples_get_no_weight() {
+        let samples = timed(&[(200.0, 10.0), (150.0, 90.0), (400.0, 30.0)]);
+        assert_close(time_weighted_mean(&samples).unwrap(), 20.0);
+    }
+
+    #[test]
+    fn empty_samples_are_all_zeros() {
+        assert_eq!(SampleStats::from_samples(&[]), SampleStats::default());
+        assert_eq!(SampleStats::from_timed_samples(&[]), SampleStats::default());
+    }
+}
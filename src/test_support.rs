@@ -0,0 +1,149 @@
+//! Builders shared by the unit tests.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    adapter::{Adapter, Luid},
+    aggregate::summarize_passes,
+    engine::{Measurement, Series},
+    plan::{PassKind, PassOrder},
+    process::Process,
+    results::{PassResult, RunConfig, RunResults},
+    sampling::{Sample, SourceSamples},
+    scenario::{PassSpec, Scenario, WorkloadSettings},
+    stats::SampleStats,
+};
+
+pub fn adapter(name: &str, low_part: u32) -> Adapter {
+    Adapter {
+        name: name.to_owned(),
+        luid: Luid {
+            high_part: 0,
+            low_part,
+        },
+    }
+}
+
+pub fn process(name: &str, pid: u32) -> Process {
+    Process {
+        name: name.to_owned(),
+        pid,
+    }
+}
+
+pub fn utilization(process_index: usize, adapter_index: usize, engine: &str) -> Series {
+    Series {
+        process_index,
+        adapter_index: Some(adapter_index),
+        measurement: Measurement::Utilization {
+            engine: engine.to_owned(),
+        },
+    }
+}
+
+/// Samples taken every 250ms.
+pub fn samples(values: &[f64]) -> SourceSamples {
+    SourceSamples {
+        instances: Vec::new(),
+        samples: values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Sample::new(Duration::from_millis(250 * (i as u64 + 1)), *value))
+            .collect(),
+    }
+}
+
+/// A one second pass with the given sample values for each series.
+pub fn pass_result(
+    name: &str,
+    kind: PassKind,
+    frames: Option<usize>,
+    series: &[Series],
+    values: &[&[f64]],
+) -> PassResult {
+    let mut pass = PassSpec::new(kind, 1000, 0);
+    pass.name = name.to_owned();
+    let samples = values
+        .iter()
+        .map(|values| {
+            let samples = samples(values);
+            (SampleStats::from_timed_samples(&samples.samples), samples)
+        })
+        .collect();
+    PassResult::new(&pass, 0, frames, series, samples)
+}
+
+/// A run of the given passes, summarized like a live run.
+pub fn run_results(
+    adapters: Vec<Adapter>,
+    processes: Vec<Process>,
+    passes: Vec<PassResult>,
+) -> RunResults {
+    let summary = summarize_passes(&passes, 0.05);
+    let config = RunConfig {
+        monitor: None,
+        seed: 0,
+        alpha: 0.05,
+        engines: vec!["3D".to_owned()],
+        memory: false,
+        cpu: false,
+        scenario: Scenario {
+            name: None,
+            iterations: 1,
+            order: PassOrder::Fixed,
+            seed: None,
+            sample_interval_ms: 250,
+            workload: WorkloadSettings::default(),
+            passes: Vec::new(),
+        },
+    };
+    RunResults::new(config, None, adapters, processes, passes, summary)
+}
+
+/// A directory in the temp directory that is removed on drop. Each test
+/// needs its own name, since tests run in parallel.
+pub struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("wgcperf-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A fake proc filesystem in a `TempDir`.
+pub struct ProcTree {
+    dir: TempDir,
+}
+
+impl ProcTree {
+    pub fn new(name: &str) -> Self {
+        Self {
+            dir: TempDir::new(&format!("proc-{}", name)),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.dir.path
+    }
+
+    /// Writes `<root>/<pid>/<path>`, creating its directories.
+    pub fn write(&self, pid: u32, path: &str, contents: impl AsRef<[u8]>) {
+        let path = self.dir.path.join(pid.to_string()).join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+}
@@ -0,0 +1,649 @@
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use crate::{compare::ResultsComparison, engine::Series, results::RunResults};
+
+/// The exit code used when a run or comparison fails a threshold. Clap
+/// already uses 2 for usage errors.
+pub const THRESHOLD_FAILURE_EXIT_CODE: u8 = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// The mean utilization of the pass, in percent.
+    Utilization,
+    /// The mean utilization of the pass minus the baseline's, in percentage
+    /// points.
+    Overhead,
+    /// The mean number of frames captured per iteration.
+    Frames,
+    /// The change in mean utilization from the old results file, in
+    /// percentage points. Only available when comparing.
+    Delta,
+    /// The change in mean utilization relative to the old results file, in
+    /// percent. Only available when comparing.
+    Change,
+}
+
+impl Metric {
+    const ALL: [Metric; 5] = [
+        Metric::Utilization,
+        Metric::Overhead,
+        Metric::Frames,
+        Metric::Delta,
+        Metric::Change,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Utilization => "utilization",
+            Metric::Overhead => "overhead",
+            Metric::Frames => "frames",
+            Metric::Delta => "delta",
+            Metric::Change => "change",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Comparator {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparator {
+    // Two character operators come first so that ">=" isn't read as ">".
+    const ALL: [Comparator; 4] = [
+        Comparator::GreaterOrEqual,
+        Comparator::LessOrEqual,
+        Comparator::Greater,
+        Comparator::Less,
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparator::Greater => ">",
+            Comparator::GreaterOrEqual => ">=",
+            Comparator::Less => "<",
+            Comparator::LessOrEqual => "<=",
+        }
+    }
+
+    pub fn matches(self, value: f64, limit: f64) -> bool {
+        match self {
+            Comparator::Greater => value > limit,
+            Comparator::GreaterOrEqual => value >= limit,
+            Comparator::Less => value < limit,
+            Comparator::LessOrEqual => value <= limit,
+        }
+    }
+}
+
+/// A failure condition such as `wgc.overhead > 3` or `dda.frames < 90%`,
+/// where the first part is the name of a pass in the scenario. For frames,
+/// a `%` suffix makes the limit relative to the number of frames the
+/// monitor would present over the pass.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Threshold {
+    pub pass: String,
+    pub metric: Metric,
+    pub comparator: Comparator,
+    pub limit: f64,
+    pub relative: bool,
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (comparator, index) = Comparator::ALL
+            .into_iter()
+            .find_map(|comparator| s.find(comparator.symbol()).map(|i| (comparator, i)))
+            .ok_or_else(|| format!("\"{}\" has no comparison operator", s))?;
+        let target = s[..index].trim();
+        let limit = s[index + comparator.symbol().len()..].trim();
+
+        // Pass names may contain dots, metric names don't
+        let (pass, metric) = target
+            .rsplit_once('.')
+            .ok_or_else(|| format!("\"{}\" should look like <pass>.<metric>", target))?;
+        let pass = pass.trim();
+        if pass.is_empty() {
+            return Err(format!("\"{}\" has no pass name", target));
+        }
+        let metric = Metric::ALL
+            .into_iter()
+            .find(|x| x.name() == metric.trim().to_lowercase())
+            .ok_or_else(|| format!("Unknown metric \"{}\"", metric.trim()))?;
+
+        let (limit, relative) = match limit.strip_suffix('%') {
+            Some(limit) => (limit.trim(), metric == Metric::Frames),
+            None => (limit, false),
+        };
+        let limit: f64 = limit
+            .parse()
+            .map_err(|_| format!("\"{}\" is not a number", limit))?;
+
+        Ok(Self {
+            pass: pass.to_owned(),
+            metric,
+            comparator,
+            limit,
+            relative,
+        })
+    }
+}
+
+impl Display for Threshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{} {} {}{}",
+            self.pass,
+            self.metric.name(),
+            self.comparator.symbol(),
+            self.limit,
+            if self.relative { "%" } else { "" }
+        )
+    }
+}
+
+/// Reads thresholds from a file with one threshold per line. Blank lines and
+/// lines starting with `#` are ignored.
+pub fn load_thresholds<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<Threshold>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut thresholds = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let threshold = line.parse().map_err(|error| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Line {}: {}", i + 1, error),
+            )
+        })?;
+        thresholds.push(threshold);
+    }
+    Ok(thresholds)
+}
+
+#[derive(Clone, Debug)]
+pub struct Violation {
+    pub threshold: Threshold,
+    /// The adapter and engine type the value was measured on, if the metric
+    /// is per series.
+    pub adapter: Option<String>,
+    pub value: f64,
+}
+
+/// Why a threshold could not be evaluated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// No pass has the threshold's pass name, e.g. because of a typo.
+    UnknownPass,
+    /// The pass has nothing to measure, e.g. because it didn't capture.
+    NoData,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Evaluation {
+    pub violations: Vec<Violation>,
+    /// Thresholds that could not be evaluated. These count as failures so
+    /// that a typo can't silently disable a check.
+    pub skipped: Vec<(Threshold, SkipReason)>,
+}
+
+impl Evaluation {
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty() && self.skipped.is_empty()
+    }
+}
+
+/// Checks the thresholds against a single run.
+pub fn evaluate_run(thresholds: &[Threshold], results: &RunResults) -> Evaluation {
+    let mut evaluation = Evaluation::default();
+    for threshold in thresholds {
+        if let Err(reason) = evaluate_run_threshold(threshold, results, &mut evaluation.violations)
+        {
+            evaluation.skipped.push((threshold.clone(), reason));
+        }
+    }
+    evaluation
+}
+
+/// Checks the thresholds against a comparison of two runs. Metrics that
+/// describe a single run are measured on the new run.
+pub fn evaluate_comparison(
+    thresholds: &[Threshold],
+    new: &RunResults,
+    comparison: &ResultsComparison,
+) -> Evaluation {
+    let mut evaluation = Evaluation::default();
+    for threshold in thresholds {
+        let result = match threshold.metric {
+            Metric::Delta | Metric::Change => {
+                evaluate_comparison_threshold(threshold, comparison, &mut evaluation.violations)
+            }
+            _ => evaluate_run_threshold(threshold, new, &mut evaluation.violations),
+        };
+        if let Err(reason) = result {
+            evaluation.skipped.push((threshold.clone(), reason));
+        }
+    }
+    evaluation
+}
+
+fn evaluate_run_threshold(
+    threshold: &Threshold,
+    results: &RunResults,
+    violations: &mut Vec<Violation>,
+) -> Result<(), SkipReason> {
+    let Some(summary) = results
+        .summary
+        .iter()
+        .find(|x| x.name.eq_ignore_ascii_case(&threshold.pass))
+    else {
+        return Err(SkipReason::UnknownPass);
+    };
+    match threshold.metric {
+        Metric::Utilization | Metric::Overhead => {
+            // Memory series are not checked
+            let utilization: Vec<_> = summary
+                .adapters
+                .iter()
+                .filter(|x| x.series.measurement.is_utilization())
+                .collect();
+            let mut checked = false;
+            for adapter_summary in utilization {
+                let value = if threshold.metric == Metric::Utilization {
+                    adapter_summary.pooled.mean
+                } else if let Some(comparison) = &adapter_summary.comparison {
+                    comparison.delta
+                } else {
+                    // Baseline passes have no overhead
+                    continue;
+                };
+                check(
+                    threshold,
+                    value,
+                    series_label(results, &adapter_summary.series),
+                    violations,
+                );
+                checked = true;
+            }
+            if checked {
+                Ok(())
+            } else {
+                Err(SkipReason::NoData)
+            }
+        }
+        Metric::Frames => {
+            let Some(mean_frames) = summary.mean_frames else {
+                return Err(SkipReason::NoData);
+            };
+            let value = if threshold.relative {
+                let Some(monitor) = &results.monitor else {
+                    return Err(SkipReason::NoData);
+                };
+                let expected_frames =
+                    monitor.frequency as f64 * summary.duration_ms as f64 / 1000.0;
+                if expected_frames <= 0.0 {
+                    return Err(SkipReason::NoData);
+                }
+                mean_frames / expected_frames * 100.0
+            } else {
+                mean_frames
+            };
+            check(threshold, value, None, violations);
+            Ok(())
+        }
+        Metric::Delta | Metric::Change => Err(SkipReason::NoData),
+    }
+}
+
+fn evaluate_comparison_threshold(
+    threshold: &Threshold,
+    comparison: &ResultsComparison,
+    violations: &mut Vec<Violation>,
+) -> Result<(), SkipReason> {
+    let Some(pass) = comparison
+        .passes
+        .iter()
+        .find(|x| x.name.eq_ignore_ascii_case(&threshold.pass))
+    else {
+        return Err(SkipReason::UnknownPass);
+    };
+    let multiple_processes = pass
+        .adapters
+        .iter()
+        .any(|x| x.process != pass.adapters[0].process);
+    let mut checked = false;
+    for adapter in pass
+        .adapters
+        .iter()
+        .filter(|x| x.measurement.is_utilization())
+    {
+        let value = if threshold.metric == Metric::Delta {
+            adapter.delta
+        } else if let Some(percent_change) = adapter.percent_change {
+            percent_change
+        } else {
+            // The old mean was zero
+            continue;
+        };
+        check(
+            threshold,
+            value,
+            adapter.name.as_ref().map(|x| {
+                let label = format!("{} ({})", x, adapter.measurement.label());
+                if multiple_processes {
+                    format!("{} - {}", label, adapter.process)
+                } else {
+                    label
+                }
+            }),
+            violations,
+        );
+        checked = true;
+    }
+    // No series matched across the files, or none had a relative change
+    if checked {
+        Ok(())
+    } else {
+        Err(SkipReason::NoData)
+    }
+}
+
+fn check(
+    threshold: &Threshold,
+    value: f64,
+    adapter: Option<String>,
+    violations: &mut Vec<Violation>,
+) {
+    if threshold.comparator.matches(value, threshold.limit) {
+        violations.push(Violation {
+            threshold: threshold.clone(),
+            adapter,
+            value,
+        });
+    }
+}
+
+/// Names the adapter and engine type of a series, followed by the process
+/// if more than one was recorded.
+fn series_label(results: &RunResults, series: &Series) -> Option<String> {
+    let adapter = results.adapters.get(series.adapter_index?)?;
+    let label = format!("{} ({})", adapter.name, series.measurement.label());
+    match results.processes.get(series.process_index) {
+        Some(process) if results.processes.len() > 1 => Some(format!("{} - {}", label, process)),
+        _ => Some(label),
+    }
+}
+
+pub fn print_evaluation(evaluation: &Evaluation) {
+    if evaluation.passed() {
+        println!("All thresholds passed");
+    } else {
+        println!("Threshold failures:");
+        for (threshold, reason) in &evaluation.skipped {
+            match reason {
+                SkipReason::UnknownPass => println!(
+                    "  {}: there is no pass named \"{}\"",
+                    threshold, threshold.pass
+                ),
+                SkipReason::NoData => println!("  {}: no data to check", threshold),
+            }
+        }
+        for violation in &evaluation.violations {
+            let unit = match violation.threshold.metric {
+                Metric::Utilization | Metric::Change => "%",
+                Metric::Overhead | Metric::Delta => " points",
+                Metric::Frames if violation.threshold.relative => "% of expected",
+                Metric::Frames => " frames",
+            };
+            if let Some(adapter) = &violation.adapter {
+                println!(
+                    "  {}: {:.2}{} - {}",
+                    violation.threshold, violation.value, unit, adapter
+                );
+            } else {
+                println!("  {}: {:.2}{}", violation.threshold, violation.value, unit);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compare::compare_results,
+        engine::Measurement,
+        plan::PassKind,
+        test_support::{adapter, pass_result, process, run_results, utilization},
+    };
+
+    use super::*;
+
+    /// A run with a single baseline pass at 10% 3D utilization.
+    fn baseline_run() -> RunResults {
+        run_with_wgc_pass(None)
+    }
+
+    /// A run with a baseline pass at 10% 3D utilization and, if given, a
+    /// WGC pass at the given utilization.
+    fn run_with_wgc_pass(wgc: Option<f64>) -> RunResults {
+        let series = [utilization(0, 0, "3D")];
+        let mut passes = vec![pass_result(
+            "baseline",
+            PassKind::Baseline,
+            None,
+            &series,
+            &[&[10.0; 4]],
+        )];
+        if let Some(wgc) = wgc {
+            passes.push(pass_result(
+                "wgc",
+                PassKind::Wgc,
+                Some(60),
+                &series,
+                &[&[wgc; 4]],
+            ));
+        }
+        run_results(vec![adapter("GPU", 1)], vec![process("dwm.exe", 1)], passes)
+    }
+
+    fn thresholds(conditions: &[&str]) -> Vec<Threshold> {
+        conditions.iter().map(|x| x.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn parses_thresholds() {
+        let threshold: Threshold = "wgc.overhead >= 3".parse().unwrap();
+        assert_eq!(
+            threshold,
+            Threshold {
+                pass: "wgc".to_owned(),
+                metric: Metric::Overhead,
+                comparator: Comparator::GreaterOrEqual,
+                limit: 3.0,
+                relative: false,
+            }
+        );
+        let threshold: Threshold = "dda.frames<=90%".parse().unwrap();
+        assert_eq!(threshold.comparator, Comparator::LessOrEqual);
+        assert_eq!(threshold.limit, 90.0);
+        assert!(threshold.relative);
+        assert_eq!(threshold.to_string(), "dda.frames <= 90%");
+
+        let comparators: Vec<_> = thresholds(&["a.delta > 1", "a.delta < 1", "a.delta <= -1"])
+            .iter()
+            .map(|x| (x.comparator, x.limit))
+            .collect();
+        assert_eq!(
+            comparators,
+            [
+                (Comparator::Greater, 1.0),
+                (Comparator::Less, 1.0),
+                (Comparator::LessOrEqual, -1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn only_frames_can_be_relative() {
+        let threshold: Threshold = "wgc.change > 5%".parse().unwrap();
+        assert_eq!(threshold.limit, 5.0);
+        assert!(!threshold.relative);
+        assert_eq!(threshold.to_string(), "wgc.change > 5");
+    }
+
+    #[test]
+    fn pass_names_can_contain_dots() {
+        let threshold: Threshold = "wgc.dirty.rects.Utilization > 20".parse().unwrap();
+        assert_eq!(threshold.pass, "wgc.dirty.rects");
+        assert_eq!(threshold.metric, Metric::Utilization);
+    }
+
+    #[test]
+    fn rejects_malformed_thresholds() {
+        for condition in [
+            "wgc.overhead 3",
+            "overhead > 3",
+            ".overhead > 3",
+            "wgc.speed > 3",
+            "wgc.overhead > three",
+            "wgc.overhead >",
+        ] {
+            assert!(condition.parse::<Threshold>().is_err(), "{}", condition);
+        }
+    }
+
+    #[test]
+    fn utilization_over_the_limit_fails() {
+        let results = baseline_run();
+        assert!(evaluate_run(&thresholds(&["baseline.utilization > 20"]), &results).passed());
+        let evaluation = evaluate_run(&thresholds(&["baseline.utilization > 5"]), &results);
+        assert!(!evaluation.passed());
+        assert_eq!(evaluation.violations.len(), 1);
+        assert_eq!(evaluation.violations[0].value, 10.0);
+    }
+
+    #[test]
+    fn overhead_is_measured_against_the_baseline() {
+        let results = run_with_wgc_pass(Some(13.0));
+        assert!(evaluate_run(&thresholds(&["wgc.overhead > 5"]), &results).passed());
+        let evaluation = evaluate_run(&thresholds(&["wgc.overhead > 2"]), &results);
+        assert_eq!(evaluation.violations[0].value, 3.0);
+        assert_eq!(
+            evaluation.violations[0].adapter.as_deref(),
+            Some("GPU (3D)")
+        );
+    }
+
+    #[test]
+    fn unknown_pass_fails() {
+        let evaluation = evaluate_run(&thresholds(&["basline.utilization > 20"]), &baseline_run());
+        assert!(!evaluation.passed());
+        assert_eq!(evaluation.skipped[0].1, SkipReason::UnknownPass);
+    }
+
+    #[test]
+    fn threshold_without_data_fails() {
+        // Baseline passes have no overhead or frames, and single runs have
+        // no change
+        let evaluation = evaluate_run(
+            &thresholds(&[
+                "baseline.overhead > 1",
+                "baseline.frames < 10",
+                "baseline.delta > 1",
+            ]),
+            &baseline_run(),
+        );
+        assert!(!evaluation.passed());
+        let reasons: Vec<_> = evaluation.skipped.iter().map(|x| x.1).collect();
+        assert_eq!(reasons, [SkipReason::NoData; 3]);
+    }
+
+    #[test]
+    fn utilization_without_utilization_series_fails() {
+        let cpu = [Series {
+            process_index: 0,
+            adapter_index: None,
+            measurement: Measurement::Cpu,
+        }];
+        let results = run_results(
+            Vec::new(),
+            vec![process("dwm.exe", 1)],
+            vec![pass_result(
+                "baseline",
+                PassKind::Baseline,
+                None,
+                &cpu,
+                &[&[10.0; 4]],
+            )],
+        );
+        let evaluation = evaluate_run(&thresholds(&["baseline.utilization > 50"]), &results);
+        assert_eq!(evaluation.skipped[0].1, SkipReason::NoData);
+    }
+
+    #[test]
+    fn comparisons_check_the_change_between_runs() {
+        let old = run_with_wgc_pass(Some(20.0));
+        let new = run_with_wgc_pass(Some(22.0));
+        let comparison = compare_results(&old, &new, 0.05);
+
+        let evaluation = evaluate_comparison(
+            &thresholds(&["wgc.delta > 5", "wgc.change > 20", "wgc.utilization > 30"]),
+            &new,
+            &comparison,
+        );
+        assert!(evaluation.passed());
+
+        let evaluation = evaluate_comparison(
+            &thresholds(&["wgc.delta > 1", "wgc.change >= 10", "wgc.utilization > 21"]),
+            &new,
+            &comparison,
+        );
+        let values: Vec<_> = evaluation
+            .violations
+            .iter()
+            .map(|x| (x.threshold.metric, x.value))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (Metric::Delta, 2.0),
+                (Metric::Change, 10.0),
+                (Metric::Utilization, 22.0)
+            ]
+        );
+        assert!(evaluation.skipped.is_empty());
+    }
+
+    #[test]
+    fn comparisons_without_data_fail() {
+        // The old run was idle, so there is no relative change
+        let old = run_with_wgc_pass(Some(0.0));
+        let new = run_with_wgc_pass(Some(22.0));
+        let comparison = compare_results(&old, &new, 0.05);
+        let evaluation = evaluate_comparison(
+            &thresholds(&["wgc.change > 5", "wgc.delta > 30", "dda.delta > 1"]),
+            &new,
+            &comparison,
+        );
+        assert!(!evaluation.passed());
+        assert!(evaluation.violations.is_empty());
+        let reasons: Vec<_> = evaluation.skipped.iter().map(|x| x.1).collect();
+        assert_eq!(reasons, [SkipReason::NoData, SkipReason::UnknownPass]);
+
+        // No series matched across the files
+        let mut renamed = run_with_wgc_pass(Some(22.0));
+        renamed.processes[0].name = "gnome-shell".to_owned();
+        let comparison = compare_results(&old, &renamed, 0.05);
+        let evaluation =
+            evaluate_comparison(&thresholds(&["wgc.delta > 1"]), &renamed, &comparison);
+        assert_eq!(evaluation.skipped[0].1, SkipReason::NoData);
+    }
+}
@@ -0,0 +1,421 @@
+use std::{fmt::Display, io::BufRead, path::Path, time::Duration};
+
+use crate::{
+    adapter::{Adapter, Luid},
+    engine::{GpuEngineInstance, Measurement, Series},
+    sampling::{Sample, SourceSamples},
+};
+
+/// A performance counter log exported by `typeperf` or `relog` in the
+/// `PDH-CSV 4.0` format: a header row with a timestamp column followed by
+/// one column per counter path, then one row per sample.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CounterLog {
+    /// The full path of each counter column, e.g.
+    /// `\\MACHINE\GPU Engine(pid_..._engtype_3D)\Utilization Percentage`.
+    pub counters: Vec<String>,
+    pub rows: Vec<CounterRow>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CounterRow {
+    /// The time since the first row.
+    pub elapsed_ms: f64,
+    /// One value per counter. Counters are left empty when the instance
+    /// didn't exist at the time, and on the first row for rate counters.
+    pub values: Vec<Option<f64>>,
+}
+
+#[derive(Debug)]
+pub enum CounterLogError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl Display for CounterLogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CounterLogError::Io(error) => write!(f, "{}", error),
+            CounterLogError::Parse { line, message } => write!(f, "Line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for CounterLogError {}
+
+impl From<std::io::Error> for CounterLogError {
+    fn from(value: std::io::Error) -> Self {
+        CounterLogError::Io(value)
+    }
+}
+
+/// The GPU engine utilization of one process in a log, laid out like the
+/// series of a live run.
+#[derive(Clone, Debug)]
+pub struct LogSamples {
+    /// One adapter per LUID, in the order they first appear in the log. Logs
+    /// don't record adapter names, so adapters are named by LUID.
+    pub adapters: Vec<Adapter>,
+    pub series: Vec<Series>,
+    /// The samples of each series, in the order of `series`.
+    pub samples: Vec<SourceSamples>,
+    pub duration_ms: u64,
+    /// The average time between rows.
+    pub sample_interval_ms: u64,
+}
+
+impl CounterLog {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CounterLogError> {
+        let file = std::fs::File::open(path)?;
+        Self::parse(std::io::BufReader::new(file))
+    }
+
+    pub fn parse<R: BufRead>(reader: R) -> Result<Self, CounterLogError> {
+        let mut counters = None;
+        let mut rows = Vec::new();
+        let mut start_ms = None;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let parse_error = |message: String| CounterLogError::Parse {
+                line: i + 1,
+                message,
+            };
+            // relog writes a byte order mark
+            let line = line.trim_start_matches('\u{feff}').trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            let fields = split_csv_line(line).map_err(parse_error)?;
+            let Some(counters) = &counters else {
+                if fields.len() < 2 || !fields[0].starts_with("(PDH-CSV") {
+                    return Err(parse_error(
+                        "Expected a PDH-CSV header with counter paths".to_owned(),
+                    ));
+                }
+                counters = Some(fields[1..].to_vec());
+                continue;
+            };
+            if fields.len() != counters.len() + 1 {
+                return Err(parse_error(format!(
+                    "Expected {} columns, found {}",
+                    counters.len() + 1,
+                    fields.len()
+                )));
+            }
+            let timestamp_ms = parse_timestamp(&fields[0]).map_err(parse_error)?;
+            let start_ms = *start_ms.get_or_insert(timestamp_ms);
+            let values = fields[1..]
+                .iter()
+                .map(|field| match field.trim() {
+                    "" => Ok(None),
+                    value => value
+                        .parse()
+                        .map(Some)
+                        .map_err(|_| parse_error(format!("\"{}\" is not a number", value))),
+                })
+                .collect::<Result<_, _>>()?;
+            rows.push(CounterRow {
+                elapsed_ms: timestamp_ms - start_ms,
+                values,
+            });
+        }
+        let Some(counters) = counters else {
+            return Err(CounterLogError::Parse {
+                line: 1,
+                message: "The log is empty".to_owned(),
+            });
+        };
+        Ok(Self { counters, rows })
+    }
+
+    /// Maps the GPU Engine utilization columns of a process to series, one
+    /// per adapter and engine type, summing the engines of each type like a
+    /// live run does. Rows where none of a series' instances have a value
+    /// are skipped for that series.
+    pub fn gpu_engine_samples(&self, pid: u32, engines: &[String]) -> LogSamples {
+        let mut adapters: Vec<Adapter> = Vec::new();
+        // The series and the column of each of its instances
+        let mut columns: Vec<(Series, Vec<(String, usize)>)> = Vec::new();
+        for (column, path) in self.counters.iter().enumerate() {
+            if !path.contains(r"\GPU Engine(") || !path.ends_with(r"\Utilization Percentage") {
+                continue;
+            }
+            let Ok(instance) = GpuEngineInstance::from_counter_path(path) else {
+                continue;
+            };
+            if instance.pid != pid || !engines.contains(&instance.engtype) {
+                continue;
+            }
+            let adapter_index = match adapters.iter().position(|x| x.luid == instance.luid) {
+                Some(index) => index,
+                None => {
+                    adapters.push(Adapter {
+                        name: adapter_name(instance.luid),
+                        luid: instance.luid,
+                    });
+                    adapters.len() - 1
+                }
+            };
+            let series = Series {
+                process_index: 0,
+                adapter_index: Some(adapter_index),
+                measurement: Measurement::Utilization {
+                    engine: instance.engtype.clone(),
+                },
+            };
+            match columns.iter_mut().find(|(x, _)| *x == series) {
+                Some((_, instances)) => instances.push((instance.to_string(), column)),
+                None => columns.push((series, vec![(instance.to_string(), column)])),
+            }
+        }
+        // Match the order of a live run: by adapter, then by engine type
+        columns.sort_by_key(|(series, _)| {
+            let engine = match &series.measurement {
+                Measurement::Utilization { engine } => engines.iter().position(|x| x == engine),
+                _ => None,
+            };
+            (series.adapter_index, engine)
+        });
+
+        let samples = columns
+            .iter()
+            .map(|(_, instances)| SourceSamples {
+                instances: instances.iter().map(|(name, _)| name.clone()).collect(),
+                samples: self
+                    .rows
+                    .iter()
+                    .filter(|row| {
+                        instances
+                            .iter()
+                            .any(|(_, column)| row.values[*column].is_some())
+                    })
+                    .map(|row| {
+                        let values = instances
+                            .iter()
+                            .map(|(_, column)| row.values[*column].unwrap_or_default())
+                            .collect();
+                        Sample::from_instances(
+                            Duration::from_secs_f64(row.elapsed_ms / 1000.0),
+                            values,
+                        )
+                    })
+                    .collect(),
+            })
+            .collect();
+        let duration_ms = self
+            .rows
+            .last()
+            .map(|x| x.elapsed_ms.round() as u64)
+            .unwrap_or_default();
+        let sample_interval_ms = if self.rows.len() > 1 {
+            duration_ms / (self.rows.len() as u64 - 1)
+        } else {
+            duration_ms
+        };
+        LogSamples {
+            adapters,
+            series: columns.into_iter().map(|(series, _)| series).collect(),
+            samples,
+            duration_ms,
+            sample_interval_ms: sample_interval_ms.max(1),
+        }
+    }
+}
+
+fn adapter_name(luid: Luid) -> String {
+    format!("Adapter {}", luid)
+}
+
+/// Splits a line of comma separated, optionally quoted fields.
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_owned());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Parses a `MM/DD/YYYY HH:MM:SS.fff` timestamp into milliseconds since the
+/// Unix epoch. Only differences between timestamps are used, so the time
+/// zone doesn't matter.
+fn parse_timestamp(timestamp: &str) -> Result<f64, String> {
+    let invalid = || format!("\"{}\" is not a timestamp", timestamp);
+    let (date, time) = timestamp.trim().split_once(' ').ok_or_else(invalid)?;
+    let date: Vec<i64> = date
+        .split('/')
+        .map(|x| x.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let time: Vec<f64> = time
+        .split(':')
+        .map(|x| x.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let [month, day, year] = date[..] else {
+        return Err(invalid());
+    };
+    let [hours, minutes, seconds] = time[..] else {
+        return Err(invalid());
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let days = days_from_civil(year, month, day) as f64;
+    Ok((((days * 24.0 + hours) * 60.0 + minutes) * 60.0 + seconds) * 1000.0)
+}
+
+/// The number of days from 1970-01-01 to the given date in the proleptic
+/// Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `relog` export: a byte order mark, every field quoted, and `" "`
+    /// for counters without a value. The columns are out of order, and
+    /// include another process and a counter that isn't a GPU engine.
+    const LOG: &str = concat!(
+        "\u{feff}",
+        r#""(PDH-CSV 4.0) (Pacific Daylight Time)(420)","\\DESKTOP\GPU Engine(pid_1234_luid_0x00000000_0x0000C2D3_phys_0_eng_1_engtype_Copy)\Utilization Percentage","\\DESKTOP\GPU Engine(pid_1234_luid_0x00000000_0x0000D00D_phys_0_eng_0_engtype_3D)\Utilization Percentage","\\DESKTOP\GPU Engine(pid_1234_luid_0x00000000_0x0000C2D3_phys_0_eng_0_engtype_3D)\Utilization Percentage","\\DESKTOP\GPU Engine(pid_999_luid_0x00000000_0x0000C2D3_phys_0_eng_0_engtype_3D)\Utilization Percentage","\\DESKTOP\GPU Engine(pid_1234_luid_0x00000000_0x0000C2D3_phys_0_eng_3_engtype_3D)\Utilization Percentage","\\DESKTOP\Process(dwm)\% Processor Time"
+"10/18/2026 10:00:00.000"," "," "," "," "," "," "
+"10/18/2026 10:00:01.000","1.5"," ","10","50","2.5","3"
+"10/18/2026 10:00:02.005","0.5","4","20","50"," ","3"
+"10/18/2026 10:00:03.001","0","6","30","50","5","3"
+"#
+    );
+
+    fn luid(low_part: u32) -> Luid {
+        Luid {
+            high_part: 0,
+            low_part,
+        }
+    }
+
+    fn utilization(adapter_index: usize, engine: &str) -> Series {
+        Series {
+            process_index: 0,
+            adapter_index: Some(adapter_index),
+            measurement: Measurement::Utilization {
+                engine: engine.to_owned(),
+            },
+        }
+    }
+
+    fn points(samples: &SourceSamples) -> Vec<(f64, f64)> {
+        samples
+            .samples
+            .iter()
+            .map(|x| (x.elapsed_ms.round(), x.value))
+            .collect()
+    }
+
+    #[test]
+    fn parses_relog_exports() {
+        let log = CounterLog::parse(LOG.as_bytes()).unwrap();
+        assert_eq!(log.counters.len(), 6);
+        assert_eq!(
+            log.counters[5],
+            r#"\\DESKTOP\Process(dwm)\% Processor Time"#
+        );
+        let elapsed: Vec<f64> = log.rows.iter().map(|x| x.elapsed_ms.round()).collect();
+        assert_eq!(elapsed, [0.0, 1000.0, 2005.0, 3001.0]);
+        assert_eq!(log.rows[0].values, [None; 6]);
+        assert_eq!(
+            log.rows[2].values,
+            [
+                Some(0.5),
+                Some(4.0),
+                Some(20.0),
+                Some(50.0),
+                None,
+                Some(3.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn maps_gpu_engines_to_series() {
+        let log = CounterLog::parse(LOG.as_bytes()).unwrap();
+        let samples = log.gpu_engine_samples(1234, &["3D".to_owned(), "Copy".to_owned()]);
+
+        let luids: Vec<Luid> = samples.adapters.iter().map(|x| x.luid).collect();
+        assert_eq!(luids, [luid(0xC2D3), luid(0xD00D)]);
+        assert_eq!(samples.adapters[1].name, "Adapter 0x00000000_0x0000D00D");
+        // By adapter, then in the order the engines were asked for
+        assert_eq!(
+            samples.series,
+            [
+                utilization(0, "3D"),
+                utilization(0, "Copy"),
+                utilization(1, "3D")
+            ]
+        );
+
+        // Both 3D engines of the first adapter are summed, and the first
+        // row has no values at all
+        let summed = &samples.samples[0];
+        assert_eq!(
+            summed.instances,
+            [
+                "pid_1234_luid_0x00000000_0x0000C2D3_phys_0_eng_0_engtype_3D",
+                "pid_1234_luid_0x00000000_0x0000C2D3_phys_0_eng_3_engtype_3D"
+            ]
+        );
+        assert_eq!(
+            points(summed),
+            [(1000.0, 12.5), (2005.0, 20.0), (3001.0, 35.0)]
+        );
+        assert_eq!(summed.samples[1].instances, [20.0, 0.0]);
+        assert_eq!(
+            points(&samples.samples[1]),
+            [(1000.0, 1.5), (2005.0, 0.5), (3001.0, 0.0)]
+        );
+        // The second adapter has no value until the third row
+        assert_eq!(points(&samples.samples[2]), [(2005.0, 4.0), (3001.0, 6.0)]);
+
+        assert_eq!(samples.duration_ms, 3001);
+        assert_eq!(samples.sample_interval_ms, 1000);
+    }
+
+    #[test]
+    fn other_processes_and_engines_are_left_out() {
+        let log = CounterLog::parse(LOG.as_bytes()).unwrap();
+        let samples = log.gpu_engine_samples(999, &["3D".to_owned()]);
+        assert_eq!(samples.series, [utilization(0, "3D")]);
+        assert_eq!(samples.samples[0].samples.len(), 3);
+
+        let samples = log.gpu_engine_samples(1234, &["VideoDecode".to_owned()]);
+        assert!(samples.adapters.is_empty());
+        assert!(samples.series.is_empty());
+    }
+
+    #[test]
+    fn rejects_logs_without_a_header() {
+        let error = CounterLog::parse(r#""10/18/2026 10:00:00.000","1""#.as_bytes()).unwrap_err();
+        assert!(matches!(error, CounterLogError::Parse { line: 1, .. }));
+        let error = CounterLog::parse("".as_bytes()).unwrap_err();
+        assert!(matches!(error, CounterLogError::Parse { line: 1, .. }));
+    }
+}